@@ -0,0 +1,53 @@
+//! Compares the petgraph-based `PulseNetwork` with its compiled form.
+//!
+//! Runs on `input/2023/day20.txt` when present, otherwise on the puzzle's
+//! second example. Pass a number of presses as the first argument.
+
+use aoc23::day20::{parse_modules, Counter, PulseNetwork};
+use std::time::{Duration, Instant};
+
+const EXAMPLE: &[u8] = br#"broadcaster -> a
+%a -> inv, con
+&inv -> b
+%b -> con
+&con -> output"#;
+
+fn report(name: &str, presses: u64, pulses: u64, t: Duration) {
+    println!(
+        "{name:>10}: {presses} presses, {pulses} pulses in {t:?} ({:.1} Mpulses/s)",
+        pulses as f64 / t.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let input = std::fs::read("input/2023/day20.txt").unwrap_or_else(|_| EXAMPLE.to_vec());
+    let presses = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(100_000u64);
+    let modules = parse_modules(&input).unwrap();
+    let net = PulseNetwork::new(&modules).unwrap();
+
+    let mut graph = net.clone();
+    let mut counter = Counter::default();
+    let start = Instant::now();
+    for _ in 0..presses {
+        graph.push_button(&mut counter);
+    }
+    report(
+        "petgraph",
+        presses,
+        counter.low + counter.high,
+        start.elapsed(),
+    );
+
+    let mut compiled = net.compile();
+    let mut pulses = 0;
+    let start = Instant::now();
+    for _ in 0..presses {
+        let c = compiled.push_button();
+        pulses += c.low + c.high;
+    }
+    report("compiled", presses, pulses, start.elapsed());
+    assert_eq!(pulses, counter.low + counter.high);
+}
@@ -0,0 +1,101 @@
+//! Compares the brute-force and sweep counts of crossing hailstone paths
+//! for growing numbers of random stones, and reports where the sweep starts
+//! winning. The brute force checks every pair with integer sign tests, the
+//! same check the sweep falls back to. The sweep only beats pairwise checks
+//! for stones starting outside the test area, so two sets are timed: stones
+//! all around the area, and stones spread like the puzzle input, most of
+//! them inside it.
+//!
+//! The brute force is skipped above 5000 stones; pass a larger limit as
+//! the first argument to time it anyway.
+
+use aoc23::day24::{collisions_2d_within, collisions_2d_within_brute, Hail};
+use nalgebra::Vector3;
+use std::time::{Duration, Instant};
+
+const MIN: i64 = 200_000_000_000_000;
+const MAX: i64 = 400_000_000_000_000;
+
+struct Rng(u64);
+impl Rng {
+    fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        lo + (self.0 % (hi - lo + 1) as u64) as i64
+    }
+}
+
+/// Stones within `spread` of the area, keeping away from it if `outside`.
+fn stones(n: usize, outside: bool, rng: &mut Rng) -> Vec<Hail> {
+    let spread = MAX - MIN;
+    let coord = |rng: &mut Rng| rng.range(MIN - spread / 2, MAX + spread / 2);
+    (0..n)
+        .map(|_| {
+            let mut pos = Vector3::new(coord(rng), coord(rng), coord(rng));
+            while outside && (MIN..=MAX).contains(&pos.x) && (MIN..=MAX).contains(&pos.y) {
+                pos = Vector3::new(coord(rng), coord(rng), coord(rng));
+            }
+            // head roughly towards the area so that most paths cross it
+            let towards = |p: i64, rng: &mut Rng| {
+                let target = rng.range(MIN, MAX);
+                (target - p).signum() * rng.range(1, 500)
+            };
+            let vel = Vector3::new(
+                towards(pos.x, rng),
+                towards(pos.y, rng),
+                rng.range(-500, 500),
+            );
+            Hail { pos, vel }
+        })
+        .collect()
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let ret = f();
+    (ret, start.elapsed())
+}
+
+fn main() {
+    let brute_limit = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(5000);
+    let mut rng = Rng(0x853c_49e6_748f_ea9b);
+    for (name, outside, sizes) in [
+        (
+            "outside",
+            true,
+            &[10, 30, 100, 300, 1000, 3000, 5000, 30_000, 100_000][..],
+        ),
+        (
+            "puzzle-like",
+            false,
+            &[10, 30, 100, 300, 1000, 3000, 5000][..],
+        ),
+    ] {
+        println!("{name} stones:");
+        let mut crossover = None;
+        for &n in sizes {
+            let hails = stones(n, outside, &mut rng);
+            let (fast, t_fast) = time(|| collisions_2d_within(&hails, MIN, MAX));
+            print!("{n:>8}: {fast} crossings, sweep {t_fast:?}");
+            if n <= brute_limit {
+                let (slow, t_slow) = time(|| collisions_2d_within_brute(&hails, MIN, MAX));
+                assert_eq!(fast, slow);
+                print!(", brute force {t_slow:?}");
+                if t_fast < t_slow {
+                    crossover.get_or_insert(n);
+                } else {
+                    crossover = None;
+                }
+            }
+            println!();
+        }
+        match crossover {
+            Some(n) => println!("the sweep is faster from {n} stones on"),
+            None => println!("the sweep never caught up"),
+        }
+    }
+}
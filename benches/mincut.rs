@@ -0,0 +1,46 @@
+//! Times `k_edge_connected_subgraphs` on growing chains of cliques with a
+//! ring hanging off them, the shape the `many_groups` unit test checks for
+//! correctness: most of the ring has to be peeled off before any cut is
+//! looked for, and the cliques split apart one light cut at a time.
+
+use aoc23::mincut::k_edge_connected_subgraphs;
+use petgraph::graph::UnGraph;
+use std::time::Instant;
+
+/// `cliques` cliques of 10 in a row, joined by single edges, and a ring of
+/// `ring` nodes attached to the first one.
+fn graph(cliques: u32, ring: u32) -> UnGraph<(), u64> {
+    let mut g = UnGraph::default();
+    for c in 0..cliques {
+        for a in 0..10 {
+            for b in a + 1..10 {
+                g.extend_with_edges([(c * 10 + a, c * 10 + b, 1)]);
+            }
+        }
+        if c > 0 {
+            g.extend_with_edges([(c * 10 - 1, c * 10, 1)]);
+        }
+    }
+    let first = cliques * 10;
+    for i in 0..ring {
+        g.extend_with_edges([(first + i, first + (i + 1) % ring, 1)]);
+    }
+    g.extend_with_edges([(0, first, 1)]);
+    g
+}
+
+fn main() {
+    for (cliques, ring) in [(10, 100), (30, 200), (100, 1000), (300, 3000)] {
+        let g = graph(cliques, ring);
+        let start = Instant::now();
+        let sets = k_edge_connected_subgraphs(&g, |&w| w, 3);
+        let elapsed = start.elapsed();
+        assert_eq!(sets.len() as u32, cliques + ring);
+        println!(
+            "{:>6} nodes, {:>6} edges: {} groups in {elapsed:?}",
+            g.node_count(),
+            g.edge_count(),
+            sets.len()
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! Prints the day 20 module network as a Graphviz digraph.
+//!
+//! Reads `input/2023/day20.txt`. With a number of presses as the first
+//! argument, the nodes are coloured by their memory after that many presses:
+//!
+//!     cargo run --example day20_dot > graph.dot
+//!     cargo run --example day20_dot 1000 | dot -Tsvg > graph.svg
+
+use aoc23::day20::{parse_modules, PulseNetwork};
+
+fn main() -> anyhow::Result<()> {
+    let input = std::fs::read("input/2023/day20.txt")?;
+    let presses = std::env::args().nth(1).map(|a| a.parse()).transpose()?;
+    let net = PulseNetwork::new(&parse_modules(&input)?)?;
+    print!("{}", net.to_dot(presses));
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Draws the longest day 23 hike over the map, and prints the junction
+//! graph it was found in as a Graphviz digraph with the hike in red.
+//!
+//! Reads the file given as the first argument, or `input/2023/day23.txt`.
+//! Pass `--no-slopes` to hike as in part 2:
+//!
+//!     cargo run --example day23_hike > junctions.dot
+//!     cargo run --example day23_hike -- --no-slopes map.txt | dot -Tsvg > hike.svg
+
+use aoc23::day23::{build_graph2, junctions_to_dot, longest_path, parse_map, without_slopes};
+
+fn main() -> anyhow::Result<()> {
+    let mut args: Vec<_> = std::env::args().skip(1).collect();
+    let no_slopes = args.iter().any(|a| a == "--no-slopes");
+    args.retain(|a| a != "--no-slopes");
+    let path = args
+        .pop()
+        .unwrap_or_else(|| "input/2023/day23.txt".to_owned());
+    let mut map = parse_map(&std::fs::read(path)?)?;
+    if no_slopes {
+        map = without_slopes(&map);
+    }
+    let (g, start, goal) = build_graph2(&map);
+    let Some(hike) = longest_path(&g, start, goal) else {
+        anyhow::bail!("there is no way down");
+    };
+    eprint!("{}", hike.render(&map, &g));
+    eprintln!(
+        "{} steps through {} junctions",
+        hike.length,
+        hike.junctions.len()
+    );
+    print!("{}", junctions_to_dot(&g, start, goal, Some(&hike)));
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! Prints the minimum cut of a day 25 wiring diagram as a Graphviz graph.
+//!
+//! Reads the file given as the first argument, or `input/2023/day25.txt`:
+//!
+//!     cargo run --example day25_cut > cut.dot
+//!     cargo run --example day25_cut -- wiring.txt | neato -Tsvg > cut.svg
+
+use aoc23::day25::parse_wiring;
+use aoc23::mincut::stoer_wagner;
+
+fn main() -> anyhow::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input/2023/day25.txt".to_owned());
+    let g = parse_wiring(&std::fs::read(path)?)?;
+    let Some(cut) = stoer_wagner(&g, |_| 1) else {
+        anyhow::bail!("there are fewer than two components");
+    };
+    eprintln!(
+        "cutting {} wires leaves groups of {} and {}",
+        cut.weight,
+        cut.sides[0].len(),
+        cut.sides[1].len()
+    );
+    print!("{}", cut.to_dot(&g, |n| n.clone()));
+    Ok(())
+}
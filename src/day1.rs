@@ -44,9 +44,9 @@ pub fn input_digits_words(input: &str) -> Result<Vec<u32>> {
     ];
     let first_prefix = |s: &str| -> u32 {
         let mut iv = 0;
-        let mut mini = std::usize::MAX;
+        let mut mini = usize::MAX;
         for (m, v) in matchers {
-            let i = s.find(m).unwrap_or(std::usize::MAX);
+            let i = s.find(m).unwrap_or(usize::MAX);
             if i < mini {
                 mini = i;
                 iv = *v;
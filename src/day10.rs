@@ -1,25 +1,22 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 use petgraph::graph::{NodeIndex, UnGraph};
-use pom::parser::*;
 use std::collections::HashMap;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'|').map(|_| Tile::V)
-        | sym(b'-').map(|_| Tile::H)
-        | sym(b'L').map(|_| Tile::L)
-        | sym(b'J').map(|_| Tile::J)
-        | sym(b'7').map(|_| Tile::N7)
-        | sym(b'F').map(|_| Tile::F)
-        | sym(b'.').map(|_| Tile::E)
-        | sym(b'S').map(|_| Tile::S)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..)
+fn tile(b: u8) -> Option<Tile> {
+    Some(match b {
+        b'|' => Tile::V,
+        b'-' => Tile::H,
+        b'L' => Tile::L,
+        b'J' => Tile::J,
+        b'7' => Tile::N7,
+        b'F' => Tile::F,
+        b'.' => Tile::E,
+        b'S' => Tile::S,
+        _ => return None,
+    })
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -34,16 +31,9 @@ enum Tile {
     S,
 }
 
-#[derive(Debug)]
-struct Map {
-    data: Vec<Vec<Tile>>,
-}
-
 #[aoc_generator(day10)]
-fn input_gen(input: &[u8]) -> Result<Map> {
-    Ok(Map {
-        data: map().parse(input)?,
-    })
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -53,32 +43,30 @@ struct Node {
     tile: Tile,
 }
 
-fn build_graph(map: &Map) -> (UnGraph<Node, ()>, NodeIndex) {
+fn build_graph(map: &Grid<Tile>) -> (UnGraph<Node, ()>, NodeIndex) {
     use Tile::*;
     let mut s_id = None;
     let mut g = UnGraph::<Node, ()>::new_undirected();
     let mut ids = HashMap::new();
-    for (y, row) in map.data.iter().enumerate() {
-        for (x, t) in row.iter().enumerate() {
-            let n = Node { x, y, tile: *t };
-            let id = g.add_node(n);
-            if *t == S {
-                s_id = Some(id);
-            }
-            ids.insert((x, y), id);
-            if y > 0 {
-                let neigh_id = ids.get(&(n.x, n.y - 1)).unwrap();
-                let nt = map.data[y - 1][x];
-                if let (V | L | J | S, V | N7 | F | S) = (t, nt) {
-                    g.add_edge(id, *neigh_id, ());
-                }
+    for ((y, x), t) in map.indexed_iter() {
+        let n = Node { x, y, tile: *t };
+        let id = g.add_node(n);
+        if *t == S {
+            s_id = Some(id);
+        }
+        ids.insert((x, y), id);
+        if y > 0 {
+            let neigh_id = ids.get(&(n.x, n.y - 1)).unwrap();
+            let nt = map[(y - 1, x)];
+            if let (V | L | J | S, V | N7 | F | S) = (t, nt) {
+                g.add_edge(id, *neigh_id, ());
             }
-            if x > 0 {
-                let neigh_id = ids.get(&(n.x - 1, n.y)).unwrap();
-                let nt = map.data[y][x - 1];
-                if let (H | J | N7 | S, H | L | F | S) = (t, nt) {
-                    g.add_edge(id, *neigh_id, ());
-                }
+        }
+        if x > 0 {
+            let neigh_id = ids.get(&(n.x - 1, n.y)).unwrap();
+            let nt = map[(y, x - 1)];
+            if let (H | J | N7 | S, H | L | F | S) = (t, nt) {
+                g.add_edge(id, *neigh_id, ());
             }
         }
     }
@@ -95,7 +83,7 @@ fn get_loop(g: &UnGraph<Node, ()>, s_id: NodeIndex) -> Vec<NodeIndex> {
 }
 
 #[aoc(day10, part1)]
-fn part1(input: &Map) -> i64 {
+fn part1(input: &Grid<Tile>) -> i64 {
     let (g, s_id) = build_graph(input);
     let scc = get_loop(&g, s_id);
     let dists = petgraph::algo::dijkstra(&g, s_id, None, |_| 1);
@@ -150,30 +138,28 @@ fn get_s_tile(g: &UnGraph<Node, ()>, s: NodeIndex) -> Tile {
 }
 
 #[aoc(day10, part2)]
-fn part2(input: &Map) -> i64 {
+fn part2(input: &Grid<Tile>) -> i64 {
     let (g, s_id) = build_graph(input);
     let scc = get_loop(&g, s_id);
     let nodes = scc
         .iter()
         .map(|id| *g.node_weight(*id).unwrap())
         .collect_vec();
-    let mut new_map = vec![vec![Tile::E; input.data[0].len()]; input.data.len()];
+    let mut new_map = input.map(|_| Tile::E);
     for n in nodes {
-        new_map[n.y][n.x] = if n.tile == Tile::S {
+        new_map[(n.y, n.x)] = if n.tile == Tile::S {
             get_s_tile(&g, s_id)
         } else {
             n.tile
         };
     }
     let mut ret = 0;
-    for y in 0..new_map.len() {
+    for row in new_map.rows() {
         let mut n = 0;
-        for x in 0..new_map[0].len() {
-            match new_map[y][x] {
-                Tile::E => {
-                    if n % 2 == 1 {
-                        ret += 1;
-                    }
+        for t in row {
+            match t {
+                Tile::E if n % 2 == 1 => {
+                    ret += 1;
                 }
                 Tile::V | Tile::J | Tile::L => {
                     n += 1;
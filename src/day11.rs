@@ -1,16 +1,14 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use pom::parser::*;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'#').map(|_| Tile::Galaxy) | sym(b'.').map(|_| Tile::Empty)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..)
+fn tile(b: u8) -> Option<Tile> {
+    match b {
+        b'#' => Some(Tile::Galaxy),
+        b'.' => Some(Tile::Empty),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,20 +17,15 @@ enum Tile {
     Galaxy,
 }
 
-#[derive(Debug, Clone)]
-struct Map {
-    data: Vec<Vec<Tile>>,
-}
-
 #[derive(Debug, Copy, Clone)]
 struct Pos {
     x: i64,
     y: i64,
 }
 
-fn expand_rows(map: &Map, factor: i64) -> Vec<i64> {
-    map.data
-        .iter()
+fn expand_rows(map: &Grid<Tile>, factor: i64) -> Vec<i64> {
+    map.rows()
+        .into_iter()
         .map(|row| {
             if row.iter().all(|t| *t == Tile::Empty) {
                 factor
@@ -42,27 +35,25 @@ fn expand_rows(map: &Map, factor: i64) -> Vec<i64> {
         })
         .collect_vec()
 }
-fn expand_cols(map: &Map, factor: i64) -> Vec<i64> {
-    let mut cols = Vec::new();
-    for x in 0..map.data[0].len() {
-        let mut all_empty = true;
-        for y in 0..map.data.len() {
-            if map.data[y][x] != Tile::Empty {
-                all_empty = false;
-                break;
+fn expand_cols(map: &Grid<Tile>, factor: i64) -> Vec<i64> {
+    map.columns()
+        .into_iter()
+        .map(|col| {
+            if col.iter().all(|t| *t == Tile::Empty) {
+                factor
+            } else {
+                1
             }
-        }
-        cols.push(if all_empty { factor } else { 1 });
-    }
-    cols
+        })
+        .collect_vec()
 }
 
-fn get_galaxies(map: &Map, expansion_factor: i64) -> Vec<Pos> {
+fn get_galaxies(map: &Grid<Tile>, expansion_factor: i64) -> Vec<Pos> {
     let exp_rows = expand_rows(map, expansion_factor);
     let exp_cols = expand_cols(map, expansion_factor);
     let mut ret = Vec::new();
     let mut actual_y = 0;
-    for (y, row) in map.data.iter().enumerate() {
+    for (y, row) in map.rows().into_iter().enumerate() {
         let mut actual_x = 0;
         for (x, t) in row.iter().enumerate() {
             if *t == Tile::Galaxy {
@@ -79,13 +70,11 @@ fn get_galaxies(map: &Map, expansion_factor: i64) -> Vec<Pos> {
 }
 
 #[aoc_generator(day11)]
-fn input_gen(input: &[u8]) -> Result<Map> {
-    Ok(Map {
-        data: map().parse(input)?,
-    })
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
 }
 
-fn distances_with_factor(input: &Map, factor: i64) -> i64 {
+fn distances_with_factor(input: &Grid<Tile>, factor: i64) -> i64 {
     let mut ret = 0;
     let gs = get_galaxies(input, factor);
     for p in gs.into_iter().combinations(2) {
@@ -97,12 +86,12 @@ fn distances_with_factor(input: &Map, factor: i64) -> i64 {
 }
 
 #[aoc(day11, part1)]
-fn part1(input: &Map) -> i64 {
+fn part1(input: &Grid<Tile>) -> i64 {
     distances_with_factor(input, 2)
 }
 
 #[aoc(day11, part2)]
-fn part2(input: &Map) -> i64 {
+fn part2(input: &Grid<Tile>) -> i64 {
     distances_with_factor(input, 1000000)
 }
 
@@ -1,7 +1,6 @@
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
 use pom::parser::*;
-use std::collections::HashMap;
 
 fn unsigned<'a>() -> Parser<'a, u8, i64> {
     let n = (one_of(b"123456789") - one_of(b"0123456789").repeat(0..)) | sym(b'0');
@@ -30,16 +29,17 @@ fn map<'a>() -> Parser<'a, u8, Map> {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-enum Tile {
+pub enum Tile {
     Ok,
     Broken,
     Unknonw,
 }
 
 #[derive(Debug, Clone)]
-struct Row {
-    tiles: Vec<Tile>,
-    groups: Vec<i64>,
+pub struct Row {
+    pub tiles: Vec<Tile>,
+    /// The lengths of the runs of broken springs, in order.
+    pub groups: Vec<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -52,85 +52,152 @@ fn input_gen(input: &[u8]) -> Result<Map> {
     Ok(map().parse(input)?)
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct Search {
-    tiles: Vec<Tile>,
-    groups: Vec<i64>,
-    cur_streak: usize,
+/// Every way to fill in the unknown springs of a row, from [`arrangements`].
+pub struct Arrangements<'a> {
+    tiles: &'a [Tile],
+    groups: &'a [i64],
+    /// Where each group placed so far starts.
+    starts: Vec<usize>,
+    /// The first start to try for the next group, or for the last group
+    /// again after backtracking.
+    next: usize,
+    done: bool,
 }
 
-fn search(mut s: Search, cache: &mut HashMap<Search, i64>) -> i64 {
-    if let Some(&e) = cache.get(&s) {
-        return e;
+impl Arrangements<'_> {
+    /// Where the gap before group `k` starts.
+    fn gap_start(&self, k: usize) -> usize {
+        match k {
+            0 => 0,
+            _ => self.starts[k - 1] + self.groups[k - 1] as usize + 1,
+        }
+    }
+    fn fits(&self, start: usize, len: usize) -> bool {
+        let end = start + len;
+        end <= self.tiles.len()
+            && !self.tiles[start..end].contains(&Tile::Ok)
+            && self.tiles.get(end) != Some(&Tile::Broken)
     }
-    let key = s.clone();
-    let mut ret = 0;
-    let mut group = s.groups.first().copied().unwrap_or(0) as usize;
-    for (i, &(mut t)) in s.tiles.iter().enumerate() {
-        if t == Tile::Unknonw {
-            let mut new_search = Search {
-                tiles: s.tiles[i..].to_owned(),
-                groups: s.groups.clone(),
-                cur_streak: s.cur_streak,
-            };
-            new_search.tiles[0] = Tile::Ok;
-            ret += search(new_search, cache);
-            t = Tile::Broken;
+    /// Moves the last group placed one tile on, or finishes if there's none.
+    fn backtrack(&mut self) {
+        match self.starts.pop() {
+            Some(s) => self.next = s + 1,
+            None => self.done = true,
         }
-        match t {
-            Tile::Broken => {
-                if s.groups.is_empty() {
-                    cache.insert(key, ret);
-                    return ret;
-                }
-                s.cur_streak += 1;
-                if s.cur_streak > group {
-                    cache.insert(key, ret);
-                    return ret;
+    }
+}
+
+impl Iterator for Arrangements<'_> {
+    /// The row with every spring known to be either [`Tile::Ok`] or
+    /// [`Tile::Broken`].
+    type Item = Vec<Tile>;
+
+    fn next(&mut self) -> Option<Vec<Tile>> {
+        while !self.done {
+            let k = self.starts.len();
+            let gap = self.gap_start(k);
+            if k == self.groups.len() {
+                let found = (!self.tiles[gap.min(self.tiles.len())..].contains(&Tile::Broken))
+                    .then(|| {
+                        let mut row = vec![Tile::Ok; self.tiles.len()];
+                        for (&s, &g) in self.starts.iter().zip(self.groups) {
+                            row[s..s + g as usize].fill(Tile::Broken);
+                        }
+                        row
+                    });
+                self.backtrack();
+                if found.is_some() {
+                    return found;
                 }
+                continue;
             }
-            Tile::Ok => {
-                if s.cur_streak != 0 {
-                    if s.cur_streak == group {
-                        s.cur_streak = 0;
-                        s.groups.remove(0);
-                        group = s.groups.first().copied().unwrap_or(0) as usize;
-                    } else {
-                        cache.insert(key, ret);
-                        return ret;
-                    }
+            let len = self.groups[k] as usize;
+            // a broken spring in the gap has to belong to this group, so
+            // the group can't start after it
+            let last = (gap..self.tiles.len())
+                .find(|&i| self.tiles[i] == Tile::Broken)
+                .unwrap_or(self.tiles.len());
+            match (self.next..=last).find(|&j| self.fits(j, len)) {
+                Some(j) => {
+                    self.starts.push(j);
+                    self.next = j + len + 1;
                 }
-            }
-            _ => {
-                unreachable!();
+                None => self.backtrack(),
             }
         }
+        None
     }
-    if s.groups.is_empty() {
-        ret += 1;
+}
+
+/// Lists the arrangements of broken springs matching `tiles` and
+/// `groups` lazily, with the groups as far left as possible first.
+pub fn arrangements<'a>(tiles: &'a [Tile], groups: &'a [i64]) -> Arrangements<'a> {
+    Arrangements {
+        tiles,
+        groups,
+        starts: Vec::with_capacity(groups.len()),
+        next: 0,
+        done: false,
     }
-    cache.insert(key, ret);
-    ret
-}
-
-fn do_search(input: Map) -> i64 {
-    let mut ret = 0;
-    let mut cache = HashMap::new();
-    for row in &input.data {
-        let Row { mut tiles, groups } = row.clone();
-        tiles.push(Tile::Ok);
-        let s = Search {
-            tiles,
-            groups,
-            cur_streak: 0,
+}
+
+/// Counts the arrangements of broken springs matching `tiles` and
+/// `groups`.
+///
+/// After placing the first `k` groups, `ways[i]` is how many arrangements
+/// of those groups fit in `tiles[..i]` leaving tile `i` free. Each group
+/// extends the counts one position at a time: tile `i - 1` is either
+/// working, or the gap after a group ending there. `scratch` holds two
+/// rows of counts and is kept between calls, so counting a row no longer
+/// than the ones before doesn't allocate.
+pub fn count_arrangements(tiles: &[Tile], groups: &[i64], scratch: &mut Vec<i64>) -> i64 {
+    let n = tiles.len();
+    scratch.clear();
+    scratch.resize(2 * (n + 1), 0);
+    let (mut ways, mut next) = scratch.split_at_mut(n + 1);
+    ways[0] = 1;
+    for i in 1..=n {
+        ways[i] = if tiles[i - 1] == Tile::Broken {
+            0
+        } else {
+            ways[i - 1]
         };
-        ret += search(s, &mut cache);
     }
-    ret
+    for &g in groups {
+        let g = g as usize;
+        // how many tiles that could be broken end at `i`
+        let mut run = 0;
+        next[0] = 0;
+        for i in 1..=n {
+            let before = run;
+            run = if tiles[i - 1] == Tile::Ok { 0 } else { run + 1 };
+            next[i] = 0;
+            if tiles[i - 1] != Tile::Broken {
+                next[i] += next[i - 1];
+                if before >= g {
+                    next[i] += ways[i - 1 - g];
+                }
+            }
+            if i == n && run >= g {
+                next[i] += ways[n - g];
+            }
+        }
+        std::mem::swap(&mut ways, &mut next);
+    }
+    ways[n]
+}
+
+fn do_search(input: &Map) -> i64 {
+    let mut scratch = Vec::new();
+    input
+        .data
+        .iter()
+        .map(|row| count_arrangements(&row.tiles, &row.groups, &mut scratch))
+        .sum()
 }
 #[aoc(day12, part1)]
 fn part1(input: &Map) -> i64 {
-    do_search(input.clone())
+    do_search(input)
 }
 
 #[aoc(day12, part2)]
@@ -138,7 +205,7 @@ fn part2(input: &Map) -> i64 {
     let mut m = input.clone();
     for row in &mut m.data {
         let new_tiles = std::iter::once(Tile::Unknonw)
-            .chain(row.tiles.clone().into_iter())
+            .chain(row.tiles.clone())
             .cycle()
             .take((row.tiles.len() + 1) * 4);
         let new_groups = row
@@ -150,7 +217,7 @@ fn part2(input: &Map) -> i64 {
         row.tiles.extend(new_tiles);
         row.groups.extend(new_groups);
     }
-    do_search(m)
+    do_search(&m)
 }
 
 #[cfg(test)]
@@ -172,4 +239,83 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(&input_gen(EXAMPLE).unwrap()), 525152);
     }
+
+    fn show(tiles: &[Tile]) -> String {
+        tiles
+            .iter()
+            .map(|t| match t {
+                Tile::Ok => '.',
+                Tile::Broken => '#',
+                Tile::Unknonw => '?',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn list_arrangements() {
+        let map = input_gen(EXAMPLE).unwrap();
+        let row = &map.data[5];
+        let all: Vec<_> = arrangements(&row.tiles, &row.groups)
+            .map(|a| show(&a))
+            .collect();
+        assert_eq!(all.len(), 10);
+        assert_eq!(
+            all[..5],
+            [
+                ".###.##.#...",
+                ".###.##..#..",
+                ".###.##...#.",
+                ".###.##....#",
+                ".###..##.#..",
+            ]
+        );
+        let counts: Vec<_> = map
+            .data
+            .iter()
+            .map(|r| arrangements(&r.tiles, &r.groups).count())
+            .collect();
+        assert_eq!(counts, [1, 4, 1, 1, 4, 10]);
+        assert_eq!(arrangements(&[Tile::Broken], &[2]).next(), None);
+        assert_eq!(arrangements(&[Tile::Ok], &[]).count(), 1);
+    }
+    #[test]
+    fn random_rows() {
+        let mut seed = 0x2023_1212_u64;
+        let mut rand = |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let mut scratch = Vec::new();
+        for _ in 0..2000 {
+            let tiles: Vec<_> = (0..rand(13))
+                .map(|_| [Tile::Ok, Tile::Broken, Tile::Unknonw][rand(3) as usize])
+                .collect();
+            let groups: Vec<_> = (0..rand(5)).map(|_| rand(4) as i64 + 1).collect();
+            let row = format!("{} {groups:?}", show(&tiles));
+            let all: Vec<_> = arrangements(&tiles, &groups).collect();
+            assert_eq!(
+                count_arrangements(&tiles, &groups, &mut scratch),
+                all.len() as i64,
+                "{row}"
+            );
+            for (i, a) in all.iter().enumerate() {
+                assert!(
+                    a.iter()
+                        .zip(&tiles)
+                        .all(|(a, t)| t == a || *t == Tile::Unknonw),
+                    "{row}: {} doesn't match",
+                    show(a)
+                );
+                let runs: Vec<_> = a
+                    .split(|&t| t == Tile::Ok)
+                    .filter(|r| !r.is_empty())
+                    .map(|r| r.len() as i64)
+                    .collect();
+                assert_eq!(runs, groups, "{row}: {}", show(a));
+                assert!(i == 0 || all[i - 1] != *a, "{row}: {} repeats", show(a));
+            }
+        }
+    }
 }
@@ -1,38 +1,22 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::Itertools;
 use ndarray::{Array2, Axis};
-use pom::parser::*;
 
-fn tile<'a>() -> Parser<'a, u8, u8> {
-    sym(b'.').map(|_| 0) | sym(b'#').map(|_| 1)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<u8>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-
-fn map<'a>() -> Parser<'a, u8, Map> {
-    row().repeat(1..).map(|data| {
-        let shape = (data.len(), data[0].len());
-        let flat = data.into_iter().flatten().collect_vec();
-        Map {
-            data: Array2::from_shape_vec(shape, flat).unwrap(),
-        }
-    })
-}
-
-fn maps<'a>() -> Parser<'a, u8, Vec<Map>> {
-    list(map(), sym(b'\n'))
-}
-
-#[derive(Debug, Clone)]
-struct Map {
-    data: Array2<u8>,
+fn tile(b: u8) -> Option<u8> {
+    match b {
+        b'.' => Some(0),
+        b'#' => Some(1),
+        _ => None,
+    }
 }
 
 #[aoc_generator(day13)]
-fn input_gen(input: &[u8]) -> Result<Vec<Map>> {
-    Ok(maps().parse(input)?)
+fn input_gen(input: &[u8]) -> Result<Vec<Grid<u8>>> {
+    let text = std::str::from_utf8(input)?;
+    text.split("\n\n")
+        .map(|block| Grid::parse(block.as_bytes(), tile))
+        .collect()
 }
 
 fn find_lane_reflection(m: &Array2<u8>, axis: usize, smudges: u32) -> i64 {
@@ -59,12 +43,12 @@ fn find_lane_reflection(m: &Array2<u8>, axis: usize, smudges: u32) -> i64 {
 }
 
 #[aoc(day13, part1)]
-fn part1(input: &[Map]) -> i64 {
+fn part1(input: &[Grid<u8>]) -> i64 {
     let mut res = 0;
     for m in input {
-        let mut i = find_lane_reflection(&m.data, 1, 0);
+        let mut i = find_lane_reflection(m, 1, 0);
         if i == 0 {
-            i = find_lane_reflection(&m.data, 0, 0) * 100;
+            i = find_lane_reflection(m, 0, 0) * 100;
         }
         res += i;
     }
@@ -72,12 +56,12 @@ fn part1(input: &[Map]) -> i64 {
 }
 
 #[aoc(day13, part2)]
-fn part2(input: &[Map]) -> i64 {
+fn part2(input: &[Grid<u8>]) -> i64 {
     let mut res = 0;
     for m in input {
-        let mut i = find_lane_reflection(&m.data, 1, 1);
+        let mut i = find_lane_reflection(m, 1, 1);
         if i == 0 {
-            i = find_lane_reflection(&m.data, 0, 1) * 100;
+            i = find_lane_reflection(m, 0, 1) * 100;
         }
         res += i;
     }
@@ -1,17 +1,15 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::Itertools;
 use ndarray::{s, Array2, Axis};
-use pom::parser::*;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'O').map(|_| Tile::Round) | sym(b'#').map(|_| Tile::Cube) | sym(b'.').map(|_| Tile::Empty)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..)
+fn tile(b: u8) -> Option<Tile> {
+    match b {
+        b'O' => Some(Tile::Round),
+        b'#' => Some(Tile::Cube),
+        b'.' => Some(Tile::Empty),
+        _ => None,
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -22,11 +20,8 @@ enum Tile {
 }
 
 #[aoc_generator(day14)]
-fn input_gen(input: &[u8]) -> Result<Array2<Tile>> {
-    let data = map().parse(input)?;
-    let shape = (data.len(), data[0].len());
-    let flat = data.into_iter().flatten().collect_vec();
-    Ok(Array2::from_shape_vec(shape, flat)?)
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
 }
 
 fn tilt(map: &mut Array2<Tile>, axis: Axis, dir: i32) {
@@ -66,14 +61,14 @@ fn load(m: &Array2<Tile>) -> i64 {
 }
 
 #[aoc(day14, part1)]
-fn part1(input: &Array2<Tile>) -> i64 {
+fn part1(input: &Grid<Tile>) -> i64 {
     let mut m = input.clone();
     tilt(&mut m, Axis(1), 1);
     load(&m)
 }
 
 #[aoc(day14, part2)]
-fn part2(input: &Array2<Tile>) -> i64 {
+fn part2(input: &Grid<Tile>) -> i64 {
     let mut m = input.clone();
     let mut hist = Vec::new();
     let period;
@@ -81,7 +76,7 @@ fn part2(input: &Array2<Tile>) -> i64 {
     loop {
         hist.push(m.clone());
         cycle(&mut m);
-        if let Some(idx) = hist.iter().position(|h| h == m) {
+        if let Some(idx) = hist.iter().position(|h| *h == m) {
             periodic_from = idx;
             period = hist.len() - idx;
             break;
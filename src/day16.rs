@@ -1,20 +1,17 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
 use enumflags2::{bitflags, BitFlags};
-use pom::parser::*;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'.').map(|_| Tile::Empty)
-        | sym(b'|').map(|_| Tile::VSplit)
-        | sym(b'-').map(|_| Tile::HSplit)
-        | sym(b'\\').map(|_| Tile::LMirror)
-        | sym(b'/').map(|_| Tile::RMirror)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..) - end()
+fn tile(b: u8) -> Option<Tile> {
+    Some(match b {
+        b'.' => Tile::Empty,
+        b'|' => Tile::VSplit,
+        b'-' => Tile::HSplit,
+        b'\\' => Tile::LMirror,
+        b'/' => Tile::RMirror,
+        _ => return None,
+    })
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -43,9 +40,8 @@ struct State {
 }
 
 #[aoc_generator(day16)]
-fn input_gen(input: &[u8]) -> Result<Vec<Vec<Tile>>> {
-    let seq = map().parse(input)?;
-    Ok(seq)
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -86,13 +82,13 @@ impl Beam {
     }
 }
 
-fn energize(mut map: Vec<Vec<State>>, b: Beam) -> i64 {
-    let w = map[0].len();
-    let h = map.len();
+fn energize(mut map: Grid<State>, b: Beam) -> i64 {
+    let w = map.width();
+    let h = map.height();
     let mut stack = vec![b];
     let mut n_energized = 0;
     while let Some(mut b) = stack.pop() {
-        let s = &mut map[b.y][b.x];
+        let s = &mut map[(b.y, b.x)];
         if s.beams.contains(b.dir) {
             continue;
         }
@@ -157,18 +153,11 @@ fn energize(mut map: Vec<Vec<State>>, b: Beam) -> i64 {
     n_energized
 }
 #[aoc(day16, part1)]
-fn part1(input: &[Vec<Tile>]) -> i64 {
-    let map: Vec<Vec<State>> = input
-        .iter()
-        .map(|row| {
-            row.iter()
-                .map(|&ty| State {
-                    ty,
-                    beams: BitFlags::default(),
-                })
-                .collect()
-        })
-        .collect();
+fn part1(input: &Grid<Tile>) -> i64 {
+    let map = input.map(|&ty| State {
+        ty,
+        beams: BitFlags::default(),
+    });
     energize(
         map,
         Beam {
@@ -180,20 +169,13 @@ fn part1(input: &[Vec<Tile>]) -> i64 {
 }
 
 #[aoc(day16, part2)]
-fn part2(input: &[Vec<Tile>]) -> i64 {
-    let map: Vec<Vec<State>> = input
-        .iter()
-        .map(|row| {
-            row.iter()
-                .map(|&ty| State {
-                    ty,
-                    beams: BitFlags::default(),
-                })
-                .collect()
-        })
-        .collect();
-    let w = map[0].len();
-    let h = map.len();
+fn part2(input: &Grid<Tile>) -> i64 {
+    let map = input.map(|&ty| State {
+        ty,
+        beams: BitFlags::default(),
+    });
+    let w = map.width();
+    let h = map.height();
     let mut max = 0;
     for y in 0..h {
         max = max.max(energize(
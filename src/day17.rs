@@ -1,30 +1,20 @@
+use crate::grid::Grid;
+use crate::pathfinding::{astar, Path};
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
 use ndarray::Array2;
-use pom::parser::*;
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashSet};
 
-fn digit<'a>() -> Parser<'a, u8, u32> {
-    one_of(b"0123456789").map(|d| (d - b'0') as u32)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<u32>> {
-    digit().repeat(1..) - (sym(b'\n').discard() | end())
-}
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<u32>>> {
-    row().repeat(1..) - end()
+fn digit(b: u8) -> Option<u32> {
+    (b as char).to_digit(10)
 }
 
 #[aoc_generator(day17)]
-fn input_gen(input: &[u8]) -> Result<Array2<u32>> {
-    let data = map().parse(input)?;
-    let shape = (data.len(), data[0].len());
-    let flat = data.into_iter().flatten().collect();
-    Ok(Array2::from_shape_vec(shape, flat)?)
+fn input_gen(input: &[u8]) -> Result<Grid<u32>> {
+    Grid::parse(input, digit)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Dir {
+pub enum Dir {
     Left,
     Right,
     Up,
@@ -73,64 +63,119 @@ fn neighbours(
     ret
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-struct DistanceState {
-    dist: u32,
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Crucible {
+    pos: (usize, usize),
     dir: Dir,
-    dir_count: u32,
+    dir_count: usize,
 }
 
-fn dijkstra_constraints(
+fn crucible_path(
     map: &Array2<u32>,
     from: (usize, usize),
     to: (usize, usize),
     min_straight: usize,
     max_straight: usize,
-) -> u32 {
-    let mut queue = BinaryHeap::new();
-    queue.push(Reverse((0, from, Dir::Right, 0, vec![])));
-    let mut visited = HashSet::new();
-    while let Some(Reverse((p_d, p, p_dir, p_dir_count, preds))) = queue.pop() {
-        if p == to && p_dir_count < min_straight {
-            continue;
-        }
-        if visited.contains(&(p, p_dir, p_dir_count)) {
-            continue;
-        }
-        if p == to {
-            let mut pmap = map.clone();
-            for pred in preds {
-                pmap[pred] = 0;
-            }
-            pmap[to] = 0;
-            return p_d;
-        }
-        visited.insert((p, p_dir, p_dir_count));
-        for (n, n_dir) in neighbours(map, p, p_dir, p_dir_count, min_straight, max_straight) {
-            let n_d = map[n] + p_d;
-            let n_dir_count = if n_dir == p_dir { p_dir_count + 1 } else { 1 };
-            let mut n_preds = preds.clone();
-            n_preds.push(p);
-            queue.push(Reverse((n_d, n, n_dir, n_dir_count, n_preds)));
+) -> Option<Path<Crucible, u32>> {
+    let min_cost = map.iter().copied().min().unwrap_or(0);
+    let start = Crucible {
+        pos: from,
+        dir: Dir::Right,
+        dir_count: 0,
+    };
+    astar(
+        [start],
+        |&c| {
+            neighbours(map, c.pos, c.dir, c.dir_count, min_straight, max_straight)
+                .into_iter()
+                .map(move |(pos, dir)| {
+                    let dir_count = if dir == c.dir { c.dir_count + 1 } else { 1 };
+                    let n = Crucible {
+                        pos,
+                        dir,
+                        dir_count,
+                    };
+                    (n, map[pos])
+                })
+        },
+        |c| (c.pos.0.abs_diff(to.0) + c.pos.1.abs_diff(to.1)) as u32 * min_cost,
+        |c| c.pos == to && c.dir_count >= min_straight,
+    )
+}
+
+/// The cheapest way for a crucible to get from one block to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    /// Total heat loss.
+    pub cost: u32,
+    /// Every block entered, starting from the first block after the start.
+    pub cells: Vec<(usize, usize)>,
+    /// The direction used to enter each of `cells`.
+    pub dirs: Vec<Dir>,
+    /// The heat lost entering each of `cells`.
+    pub step_costs: Vec<u32>,
+    /// The blocks where the crucible changes direction.
+    pub turns: Vec<(usize, usize)>,
+}
+
+impl Route {
+    /// Draws the route over the map with the same arrows as the puzzle text.
+    pub fn render(&self, map: &Grid<u32>) -> String {
+        let mut glyphs = map.map(|&d| char::from_digit(d, 10).unwrap_or('?'));
+        for (&p, dir) in self.cells.iter().zip(&self.dirs) {
+            glyphs[p] = match dir {
+                Dir::Left => '<',
+                Dir::Right => '>',
+                Dir::Up => '^',
+                Dir::Down => 'v',
+            };
         }
+        glyphs.render(|&c| c)
     }
-    u32::MAX
+}
+
+/// Finds the cheapest route that moves at least `min_straight` and at most
+/// `max_straight` blocks in a line before turning, if there is one.
+pub fn dijkstra_constraints(
+    map: &Grid<u32>,
+    from: (usize, usize),
+    to: (usize, usize),
+    min_straight: usize,
+    max_straight: usize,
+) -> Option<Route> {
+    let path = crucible_path(map, from, to, min_straight, max_straight)?;
+    let moves = &path.states[1..];
+    Some(Route {
+        cost: path.cost,
+        cells: moves.iter().map(|c| c.pos).collect(),
+        dirs: moves.iter().map(|c| c.dir).collect(),
+        step_costs: moves.iter().map(|c| map[c.pos]).collect(),
+        turns: moves
+            .windows(2)
+            .filter(|w| w[0].dir != w[1].dir)
+            .map(|w| w[0].pos)
+            .collect(),
+    })
 }
 
 #[aoc(day17, part1)]
-fn part1(input: &Array2<u32>) -> u32 {
+fn part1(input: &Grid<u32>) -> u32 {
     let [h, w] = input.shape() else {
         unreachable!()
     };
     dijkstra_constraints(input, (0, 0), (h - 1, w - 1), 0, 3)
+        .expect("no route to the factory")
+        .cost
 }
 
 #[aoc(day17, part2)]
-fn part2(input: &Array2<u32>) -> u32 {
+fn part2(input: &Grid<u32>) -> u32 {
     let [h, w] = input.shape() else {
         unreachable!()
     };
     dijkstra_constraints(input, (0, 0), (h - 1, w - 1), 4, 10)
+        .expect("no route to the factory")
+        .cost
 }
 
 #[cfg(test)]
@@ -169,4 +214,36 @@ mod tests {
     fn part2_example2() {
         assert_eq!(part2(&input_gen(EXAMPLE2).unwrap()), 71);
     }
+    #[test]
+    fn route_render() {
+        let map = input_gen(EXAMPLE).unwrap();
+        let route = dijkstra_constraints(&map, (0, 0), (12, 12), 0, 3).unwrap();
+        assert_eq!(route.cost, 102);
+        assert_eq!(route.step_costs.iter().sum::<u32>(), 102);
+        assert_eq!(route.cells.len(), route.dirs.len());
+        assert_eq!(route.turns[0], (0, 2));
+        assert_eq!(
+            route.render(&map),
+            "\
+2>>34^>>>1323
+32v>>>35v>623
+325524565v>54
+3446585845v52
+4546657867v>6
+14385987984v4
+44578769877v6
+36378779796v>
+465496798688v
+456467998645v
+12246868655<v
+25465488877v5
+43226746555v>
+"
+        );
+    }
+    #[test]
+    fn no_route() {
+        let map = input_gen(b"11\n11").unwrap();
+        assert_eq!(dijkstra_constraints(&map, (0, 0), (1, 1), 4, 10), None);
+    }
 }
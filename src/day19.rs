@@ -1,7 +1,11 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use num::BigUint;
 use pom::parser::*;
 use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
 
 fn ident<'a>() -> Parser<'a, u8, String> {
     one_of(b"abcdefghijklmnopqrstuvwxyz")
@@ -10,14 +14,13 @@ fn ident<'a>() -> Parser<'a, u8, String> {
         .convert(std::str::from_utf8)
         .map(|s| s.to_owned())
 }
-fn categ<'a>() -> Parser<'a, u8, Category> {
-    sym(b'x').map(|_| Category::X)
-        | sym(b'm').map(|_| Category::M)
-        | sym(b'a').map(|_| Category::A)
-        | sym(b's').map(|_| Category::S)
-}
 fn op<'a>() -> Parser<'a, u8, Op> {
-    sym(b'>').map(|_| Op::Gt) | sym(b'<').map(|_| Op::Lt)
+    seq(b"<=").map(|_| Op::Le)
+        | seq(b">=").map(|_| Op::Ge)
+        | seq(b"==").map(|_| Op::Eq)
+        | seq(b"!=").map(|_| Op::Ne)
+        | sym(b'>').map(|_| Op::Gt)
+        | sym(b'<').map(|_| Op::Lt)
 }
 fn dec<'a>() -> Parser<'a, u8, u64> {
     ((one_of(b"123456789") - one_of(b"0123456789").repeat(0..)) | sym(b'0'))
@@ -29,154 +32,484 @@ fn dec<'a>() -> Parser<'a, u8, u64> {
 fn dest<'a>() -> Parser<'a, u8, Dest> {
     sym(b'A').map(|_| Dest::A) | sym(b'R').map(|_| Dest::R) | ident().map(Dest::Forward)
 }
-fn cond<'a>() -> Parser<'a, u8, Condition> {
-    (categ() + op() + dec() - sym(b':') + dest()).map(|(((c, o), n), d)| Condition {
-        categ: c,
-        op: o,
-        thresh: n,
-        dest: d,
-    })
+/// A workflow as written, before category names are resolved.
+type RawWorkflow = (String, Vec<(String, Op, u64, Dest)>, Dest);
+
+fn cond<'a>() -> Parser<'a, u8, (String, Op, u64, Dest)> {
+    (ident() + op() + dec() - sym(b':') + dest()).map(|(((c, o), n), d)| (c, o, n, d))
 }
-fn workflow<'a>() -> Parser<'a, u8, Workflow> {
+fn workflow<'a>() -> Parser<'a, u8, RawWorkflow> {
     (ident() - sym(b'{') + (cond() - sym(b',')).repeat(0..) + dest()
         - sym(b'}')
         - (sym(b'\n').discard() | end()))
-    .map(|((name, conditions), default)| Workflow {
-        name,
-        conditions,
-        default,
-    })
-}
-
-fn part<'a>() -> Parser<'a, u8, Part> {
-    (sym(b'{')
-        * ((seq(b"x=") * dec() - sym(b','))
-            + (seq(b"m=") * dec() - sym(b','))
-            + (seq(b"a=") * dec() - sym(b','))
-            + (seq(b"s=") * dec()))
-        - sym(b'}')
-        - (sym(b'\n').discard() | end()))
-    .map(|(((x, m), a), s)| Part {
-        scores: [x, m, a, s],
-    })
+    .map(|((name, conditions), default)| (name, conditions, default))
+}
+fn raw_workflows<'a>() -> Parser<'a, u8, Vec<RawWorkflow>> {
+    workflow().repeat(1..) - end()
 }
 
-fn system<'a>() -> Parser<'a, u8, System> {
-    (workflow().repeat(1..) - sym(b'\n') + part().repeat(1..) - end()).map(|(w, p)| System {
-        workflows: w,
-        parts: p,
-    })
+fn part<'a>() -> Parser<'a, u8, Vec<(String, u64)>> {
+    sym(b'{') * list(ident() - sym(b'=') + dec(), sym(b','))
+        - sym(b'}')
+        - (sym(b'\n').discard() | end())
+}
+fn parts<'a>() -> Parser<'a, u8, Vec<Vec<(String, u64)>>> {
+    part().repeat(0..) - end()
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Op {
+pub enum Op {
     Lt,
+    Le,
     Gt,
+    Ge,
+    Eq,
+    Ne,
 }
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Category {
-    X = 0,
-    M,
-    A,
-    S,
+impl Op {
+    fn test(self, v: u64, thresh: u64) -> bool {
+        match self {
+            Op::Lt => v < thresh,
+            Op::Le => v <= thresh,
+            Op::Gt => v > thresh,
+            Op::Ge => v >= thresh,
+            Op::Eq => v == thresh,
+            Op::Ne => v != thresh,
+        }
+    }
+    /// The operator matching exactly the values this one doesn't.
+    fn negate(self) -> Op {
+        match self {
+            Op::Lt => Op::Ge,
+            Op::Le => Op::Gt,
+            Op::Gt => Op::Le,
+            Op::Ge => Op::Lt,
+            Op::Eq => Op::Ne,
+            Op::Ne => Op::Eq,
+        }
+    }
+    /// The values matching `v op thresh`, as at most two disjoint ranges.
+    fn ranges(self, thresh: u64) -> Vec<Constraints> {
+        let below = thresh.checked_sub(1).map(|max| Constraints { min: 0, max });
+        let above = thresh
+            .checked_add(1)
+            .map(|min| Constraints { min, max: u64::MAX });
+        let from = |min| Constraints { min, max: u64::MAX };
+        let to = |max| Constraints { min: 0, max };
+        match self {
+            Op::Lt => below.into_iter().collect(),
+            Op::Le => vec![to(thresh)],
+            Op::Gt => above.into_iter().collect(),
+            Op::Ge => vec![from(thresh)],
+            Op::Eq => vec![Constraints {
+                min: thresh,
+                max: thresh,
+            }],
+            Op::Ne => below.into_iter().chain(above).collect(),
+        }
+    }
 }
+
+/// Index of a rating category in a `Schema`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Part {
-    scores: [u64; 4],
+pub struct Category(pub usize);
+
+/// The rating categories and the values each of them can take.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Schema {
+    pub names: Vec<String>,
+    pub domains: Vec<Constraints>,
+}
+impl Schema {
+    pub fn new<S: Into<String>>(categories: impl IntoIterator<Item = (S, Constraints)>) -> Schema {
+        let (names, domains) = categories.into_iter().map(|(n, d)| (n.into(), d)).unzip();
+        Schema { names, domains }
+    }
+    /// The puzzle's categories: `x`, `m`, `a` and `s`, all from 1 to 4000.
+    pub fn xmas() -> Schema {
+        Schema::new("xmas".chars().map(|c| (c, Constraints::default())))
+    }
+    pub fn index(&self, name: &str) -> Option<Category> {
+        self.names.iter().position(|n| n == name).map(Category)
+    }
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+/// Parses comma separated `name=min..=max` entries, e.g. `x=1..=4000,m=0..=9`.
+impl FromStr for Schema {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Schema> {
+        let mut categories = vec![];
+        for entry in s.split(',') {
+            let Some((name, range)) = entry.trim().split_once('=') else {
+                bail!("expected name=min..=max, got {entry:?}");
+            };
+            let Some((min, max)) = range.split_once("..=") else {
+                bail!("expected min..=max, got {range:?}");
+            };
+            let domain = Constraints {
+                min: min.parse()?,
+                max: max.parse()?,
+            };
+            if domain.min > domain.max {
+                bail!("empty domain for category {name}");
+            }
+            if categories.iter().any(|&(n, _)| n == name) {
+                bail!("category {name} is defined more than once");
+            }
+            categories.push((name, domain));
+        }
+        Ok(Schema::new(categories))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Part {
+    /// One rating per category of the schema.
+    pub scores: Vec<u64>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Dest {
+pub enum Dest {
     A,
     R,
     Forward(String),
 }
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Condition {
-    categ: Category,
-    op: Op,
-    thresh: u64,
-    dest: Dest,
+pub struct Condition {
+    pub categ: Category,
+    pub op: Op,
+    pub thresh: u64,
+    pub dest: Dest,
 }
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Workflow {
-    name: String,
-    conditions: Vec<Condition>,
-    default: Dest,
+pub struct Workflow {
+    pub name: String,
+    pub conditions: Vec<Condition>,
+    pub default: Dest,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct System {
-    workflows: Vec<Workflow>,
-    parts: Vec<Part>,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct System {
+    pub schema: Schema,
+    pub workflows: Vec<Workflow>,
+    pub parts: Vec<Part>,
+    pub program: Program,
+}
+
+impl System {
+    /// Parses a system, taking the categories from the first part, each
+    /// ranging from 1 to 4000.
+    pub fn parse(input: &[u8]) -> Result<System> {
+        System::parse_with(input, None)
+    }
+
+    /// Parses a system, with the categories from `schema` if given.
+    pub fn parse_with(input: &[u8], schema: Option<Schema>) -> Result<System> {
+        let Some(split) = input.windows(2).position(|w| w == b"\n\n") else {
+            bail!("missing blank line between workflows and parts");
+        };
+        let raw_parts = parts().parse(&input[split + 2..])?;
+        let schema = match schema {
+            Some(schema) => schema,
+            None => {
+                let Some(first) = raw_parts.first() else {
+                    bail!("no parts to take the categories from");
+                };
+                Schema::new(
+                    first
+                        .iter()
+                        .map(|(n, _)| (n.clone(), Constraints::default())),
+                )
+            }
+        };
+        let mut workflows = vec![];
+        for (name, raw_conditions, default) in raw_workflows().parse(&input[..split + 1])? {
+            let mut conditions = Vec::with_capacity(raw_conditions.len());
+            for (categ, op, thresh, dest) in raw_conditions {
+                let Some(categ) = schema.index(&categ) else {
+                    bail!("workflow {} uses unknown category {}", name, categ);
+                };
+                conditions.push(Condition {
+                    categ,
+                    op,
+                    thresh,
+                    dest,
+                });
+            }
+            workflows.push(Workflow {
+                name,
+                conditions,
+                default,
+            });
+        }
+        let mut parts = Vec::with_capacity(raw_parts.len());
+        for (i, raw) in raw_parts.into_iter().enumerate() {
+            let mut scores = vec![None; schema.len()];
+            for (name, v) in raw {
+                let Some(c) = schema.index(&name) else {
+                    bail!("part {} has unknown category {}", i + 1, name);
+                };
+                if scores[c.0].replace(v).is_some() {
+                    bail!("part {} rates {} more than once", i + 1, name);
+                }
+                let d = schema.domains[c.0];
+                if v < d.min || v > d.max {
+                    bail!(
+                        "part {} has {}={} outside {}..={}",
+                        i + 1,
+                        name,
+                        v,
+                        d.min,
+                        d.max
+                    );
+                }
+            }
+            let Some(scores) = scores.into_iter().collect() else {
+                bail!("part {} is missing some categories", i + 1);
+            };
+            parts.push(Part { scores });
+        }
+        let program = Program::compile(&workflows)?;
+        Ok(System {
+            schema,
+            workflows,
+            parts,
+            program,
+        })
+    }
 }
 
 #[aoc_generator(day19)]
 fn input_gen(input: &[u8]) -> Result<System> {
-    Ok(system().parse(input)?)
+    System::parse(input)
 }
 
-fn match_cond(c: &Condition, p: &Part) -> bool {
-    let idx = c.categ as usize;
-    let v = p.scores[idx];
-    match c.op {
-        Op::Lt => v < c.thresh,
-        Op::Gt => v > c.thresh,
-    }
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Target {
+    Accept,
+    Reject,
+    /// Index of the first instruction of a workflow.
+    Jump(usize),
 }
 
-#[aoc(day19, part1)]
-fn part1(input: &System) -> u64 {
-    let table: HashMap<_, _> = input
-        .workflows
-        .iter()
-        .map(|w| (w.name.clone(), w))
-        .collect();
-    input
-        .parts
-        .iter()
-        .filter(|p| {
-            let mut cur = "in".to_owned();
-            'main: loop {
-                let w = &table[&cur];
-                for c in &w.conditions {
-                    if match_cond(c, p) {
-                        match c.dest {
-                            Dest::A => {
-                                return true;
-                            }
-                            Dest::R => {
-                                return false;
-                            }
-                            Dest::Forward(ref next) => {
-                                cur = next.clone();
-                                continue 'main;
-                            }
-                        }
-                    }
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Instr {
+    Test {
+        categ: Category,
+        op: Op,
+        thresh: u64,
+        target: Target,
+    },
+    Goto(Target),
+}
+
+/// Workflows flattened into a single instruction array, with every
+/// `Dest::Forward` resolved to the index of the first instruction of the
+/// destination workflow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    start: usize,
+    instrs: Vec<Instr>,
+    /// Workflow names, by workflow id.
+    names: Vec<String>,
+    /// The workflow and rule each instruction was compiled from.
+    origins: Vec<(usize, Option<usize>)>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Visit {
+    New,
+    InProgress,
+    Done,
+}
+
+/// Depth-first walk of the forwarding graph, returning the first cycle found.
+fn find_cycle(
+    id: usize,
+    succs: &[Vec<usize>],
+    state: &mut [Visit],
+    path: &mut Vec<usize>,
+) -> Option<Vec<usize>> {
+    state[id] = Visit::InProgress;
+    path.push(id);
+    for &n in &succs[id] {
+        match state[n] {
+            Visit::InProgress => {
+                let from = path.iter().position(|&p| p == n).unwrap();
+                return Some(path[from..].to_vec());
+            }
+            Visit::New => {
+                if let Some(cycle) = find_cycle(n, succs, state, path) {
+                    return Some(cycle);
                 }
-                match w.default {
-                    Dest::A => {
-                        return true;
-                    }
-                    Dest::R => {
-                        return false;
+            }
+            Visit::Done => {}
+        }
+    }
+    path.pop();
+    state[id] = Visit::Done;
+    None
+}
+
+impl Program {
+    /// Rejects programs with unknown or duplicate workflow names, workflows
+    /// that can't be reached from `in`, and forwarding cycles. Cycles are
+    /// rejected even if no part could actually go around them.
+    fn compile(workflows: &[Workflow]) -> Result<Program> {
+        let mut ids = HashMap::new();
+        for (i, w) in workflows.iter().enumerate() {
+            if ids.insert(w.name.as_str(), i).is_some() {
+                bail!("workflow {} is defined more than once", w.name);
+            }
+        }
+        let Some(&start) = ids.get("in") else {
+            bail!("there is no \"in\" workflow");
+        };
+        let mut entries = Vec::with_capacity(workflows.len());
+        let mut len = 0;
+        for w in workflows {
+            entries.push(len);
+            len += w.conditions.len() + 1;
+        }
+        let mut succs = vec![vec![]; workflows.len()];
+        let mut instrs = Vec::with_capacity(len);
+        let mut origins = Vec::with_capacity(len);
+        for (i, w) in workflows.iter().enumerate() {
+            let mut target = |d: &Dest| -> Result<Target> {
+                Ok(match d {
+                    Dest::A => Target::Accept,
+                    Dest::R => Target::Reject,
+                    Dest::Forward(name) => {
+                        let Some(&id) = ids.get(name.as_str()) else {
+                            bail!("workflow {} forwards to unknown workflow {}", w.name, name);
+                        };
+                        succs[i].push(id);
+                        Target::Jump(entries[id])
                     }
-                    Dest::Forward(ref next) => {
-                        cur = next.clone();
+                })
+            };
+            for (j, c) in w.conditions.iter().enumerate() {
+                origins.push((i, Some(j)));
+                instrs.push(Instr::Test {
+                    categ: c.categ,
+                    op: c.op,
+                    thresh: c.thresh,
+                    target: target(&c.dest)?,
+                });
+            }
+            origins.push((i, None));
+            instrs.push(Instr::Goto(target(&w.default)?));
+        }
+        let mut state = vec![Visit::New; workflows.len()];
+        if let Some(cycle) = find_cycle(start, &succs, &mut state, &mut vec![]) {
+            let names: Vec<_> = cycle.iter().map(|&i| workflows[i].name.as_str()).collect();
+            bail!("workflows {} form a cycle", names.join(" -> "));
+        }
+        let unreachable: Vec<_> = workflows
+            .iter()
+            .zip(&state)
+            .filter(|(_, &s)| s == Visit::New)
+            .map(|(w, _)| w.name.as_str())
+            .collect();
+        if !unreachable.is_empty() {
+            bail!("unreachable workflows: {}", unreachable.join(", "));
+        }
+        Ok(Program {
+            start: entries[start],
+            instrs,
+            names: workflows.iter().map(|w| w.name.clone()).collect(),
+            origins,
+        })
+    }
+
+    pub fn accepts(&self, p: &Part) -> bool {
+        self.run(p, |_| ())
+    }
+
+    /// Classifies a part, also returning every rule that sent it somewhere.
+    /// The last step is the rule that accepted or rejected it.
+    pub fn explain(&self, p: &Part) -> Decision {
+        let mut path = vec![];
+        let accepted = self.run(p, |pc| {
+            let (w, rule) = self.origins[pc];
+            path.push(Step {
+                workflow: self.names[w].clone(),
+                rule,
+            })
+        });
+        Decision { accepted, path }
+    }
+
+    /// Runs the program, calling `taken` with every instruction that jumps.
+    fn run(&self, p: &Part, mut taken: impl FnMut(usize)) -> bool {
+        let mut pc = self.start;
+        loop {
+            let target = match self.instrs[pc] {
+                Instr::Test {
+                    categ,
+                    op,
+                    thresh,
+                    target,
+                } => {
+                    if !op.test(p.scores[categ.0], thresh) {
+                        pc += 1;
                         continue;
                     }
+                    target
                 }
+                Instr::Goto(target) => target,
+            };
+            taken(pc);
+            match target {
+                Target::Accept => return true,
+                Target::Reject => return false,
+                Target::Jump(next) => pc = next,
             }
-        })
+        }
+    }
+}
+
+/// A rule applied while classifying a part: the workflow and the index of
+/// the condition that matched, or `None` for the workflow's default.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Step {
+    pub workflow: String,
+    pub rule: Option<usize>,
+}
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rule {
+            Some(i) => write!(f, "{}:{}", self.workflow, i),
+            None => write!(f, "{}:default", self.workflow),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub accepted: bool,
+    pub path: Vec<Step>,
+}
+
+#[aoc(day19, part1)]
+fn part1(input: &System) -> u64 {
+    input
+        .parts
+        .iter()
+        .filter(|p| input.program.accepts(p))
         .map(|p| p.scores.iter().copied().sum::<u64>())
         .sum()
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Constraints {
-    min: u64,
-    max: u64,
+pub struct Constraints {
+    pub min: u64,
+    pub max: u64,
 }
 impl Default for Constraints {
     fn default() -> Constraints {
@@ -184,100 +517,179 @@ impl Default for Constraints {
     }
 }
 impl Constraints {
-    fn add(&self, op: Op, thresh: u64) -> Option<Constraints> {
-        let mut ret = *self;
-        match op {
-            Op::Gt => {
-                ret.min = ret.min.max(thresh + 1);
-            }
-            Op::Lt => {
-                ret.max = ret.max.min(thresh - 1);
-            }
-        }
-        (ret.min < ret.max).then_some(ret)
+    fn intersect(&self, other: &Constraints) -> Option<Constraints> {
+        let ret = Constraints {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        };
+        (ret.min <= ret.max).then_some(ret)
     }
 }
 
-#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct PartProbe {
-    scores: [Constraints; 4],
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PartProbe {
+    pub scores: Vec<Constraints>,
 }
 impl PartProbe {
-    fn constrain(&self, cond: &Condition) -> (Option<PartProbe>, Option<PartProbe>) {
-        let r1 = self.scores[cond.categ as usize]
-            .add(cond.op, cond.thresh)
-            .map(|c| {
-                let mut ret = *self;
-                ret.scores[cond.categ as usize] = c;
-                ret
-            });
-        let (rev_op, rev_thresh) = if cond.op == Op::Gt {
-            (Op::Lt, cond.thresh + 1)
-        } else {
-            (Op::Gt, cond.thresh - 1)
-        };
-        let r2 = self.scores[cond.categ as usize]
-            .add(rev_op, rev_thresh)
+    /// The pieces of this probe for which `categ op thresh` holds.
+    fn split(&self, categ: Category, op: Op, thresh: u64) -> Vec<PartProbe> {
+        op.ranges(thresh)
+            .iter()
+            .filter_map(|r| self.scores[categ.0].intersect(r))
             .map(|c| {
-                let mut ret = *self;
-                ret.scores[cond.categ as usize] = c;
+                let mut ret = self.clone();
+                ret.scores[categ.0] = c;
                 ret
-            });
-        (r1, r2)
+            })
+            .collect()
+    }
+    /// Splits the probe into the pieces that match the condition and the
+    /// ones that don't. An `==` can leave a piece on either side of the
+    /// matching value.
+    fn constrain(&self, cond: &Condition) -> (Vec<PartProbe>, Vec<PartProbe>) {
+        (
+            self.split(cond.categ, cond.op, cond.thresh),
+            self.split(cond.categ, cond.op.negate(), cond.thresh),
+        )
     }
 }
 
-#[aoc(day19, part2)]
-fn part2(input: &System) -> u64 {
-    let table: HashMap<_, _> = input
-        .workflows
-        .iter()
-        .map(|w| (w.name.clone(), w))
-        .collect();
-    let mut queue = vec![(PartProbe::default(), "in".to_owned())];
-    let mut accepted = vec![];
-    while let Some((mut p, cur)) = queue.pop() {
-        let w = &table[&cur];
-        for c in &w.conditions {
-            let (is_match, is_not_match) = p.constrain(c);
-            if let Some(newp) = is_match {
-                match c.dest {
-                    Dest::A => {
-                        accepted.push(newp);
-                    }
-                    Dest::R => {}
-                    Dest::Forward(ref next) => {
-                        queue.push((newp, next.clone()));
-                    }
+/// A box of accepted ratings, with the rules that lead to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub probe: PartProbe,
+    pub path: Vec<Step>,
+}
+impl Region {
+    pub fn combinations(&self) -> BigUint {
+        self.probe
+            .scores
+            .iter()
+            .map(|s| BigUint::from(s.max - s.min) + 1u32)
+            .product()
+    }
+}
+
+impl System {
+    /// Splits the rating space into disjoint boxes and returns the accepted ones.
+    pub fn accepted_regions(&self) -> Vec<Region> {
+        let table: HashMap<_, _> = self.workflows.iter().map(|w| (&w.name[..], w)).collect();
+        let full = PartProbe {
+            scores: self.schema.domains.clone(),
+        };
+        let mut queue = vec![(full, "in", vec![])];
+        let mut accepted = vec![];
+        while let Some((p, cur, path)) = queue.pop() {
+            let w = table[cur];
+            let mut dests = vec![];
+            let mut rest = vec![p];
+            for (i, c) in w.conditions.iter().enumerate() {
+                if rest.is_empty() {
+                    break;
+                }
+                let mut not_matching = vec![];
+                for p in rest {
+                    let (is_match, is_not_match) = p.constrain(c);
+                    dests.extend(is_match.into_iter().map(|p| (p, Some(i), &c.dest)));
+                    not_matching.extend(is_not_match);
                 }
+                rest = not_matching;
             }
-            if let Some(newp) = is_not_match {
-                p = newp;
-            } else {
-                break;
+            dests.extend(rest.into_iter().map(|p| (p, None, &w.default)));
+            for (p, rule, dest) in dests {
+                let mut path = path.clone();
+                path.push(Step {
+                    workflow: w.name.clone(),
+                    rule,
+                });
+                match dest {
+                    Dest::A => accepted.push(Region { probe: p, path }),
+                    Dest::R => {}
+                    Dest::Forward(next) => queue.push((p, next, path)),
+                }
             }
         }
-        match w.default {
-            Dest::A => {
-                accepted.push(p);
-            }
-            Dest::R => {}
-            Dest::Forward(ref next) => {
-                queue.push((p, next.clone()));
-            }
+        accepted
+    }
+}
+
+fn join_path(path: &[Step]) -> String {
+    path.iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// A JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut ret = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            c if c < ' ' => write!(ret, "\\u{:04x}", c as u32).unwrap(),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// One line per region with the inclusive bounds of each category and the
+/// space separated rule path.
+pub fn regions_csv(schema: &Schema, regions: &[Region]) -> String {
+    let mut ret = String::new();
+    for c in &schema.names {
+        let (min, max) = (format!("{c}_min"), format!("{c}_max"));
+        write!(ret, "{},{},", csv_field(&min), csv_field(&max)).unwrap();
+    }
+    ret.push_str("path\n");
+    for r in regions {
+        for s in &r.probe.scores {
+            write!(ret, "{},{},", s.min, s.max).unwrap();
         }
+        writeln!(ret, "{}", csv_field(&join_path(&r.path))).unwrap();
     }
-    let mut ret = 0;
-    for a in accepted {
-        let mut combs = 1;
-        for s in a.scores {
-            combs *= s.max - s.min + 1;
+    ret
+}
+
+/// The regions as a JSON array of `{"x": [min, max], ..., "path": [...]}` objects.
+pub fn regions_json(schema: &Schema, regions: &[Region]) -> String {
+    let mut ret = String::from("[");
+    for (i, r) in regions.iter().enumerate() {
+        if i > 0 {
+            ret.push(',');
+        }
+        ret.push('{');
+        for (c, s) in schema.names.iter().zip(&r.probe.scores) {
+            write!(ret, "{}:[{},{}],", json_string(c), s.min, s.max).unwrap();
         }
-        ret += combs;
+        let path: Vec<_> = r.path.iter().map(|s| json_string(&s.to_string())).collect();
+        write!(ret, "\"path\":[{}]}}", path.join(",")).unwrap();
     }
+    ret.push(']');
     ret
 }
 
+#[aoc(day19, part2)]
+fn part2(input: &System) -> BigUint {
+    input
+        .accepted_regions()
+        .iter()
+        .map(|r| r.combinations())
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,6 +718,213 @@ hdj{m>838:A,pv}
     }
     #[test]
     fn part2_example() {
-        assert_eq!(part2(&input_gen(EXAMPLE).unwrap()), 167409079868000);
+        assert_eq!(
+            part2(&input_gen(EXAMPLE).unwrap()),
+            BigUint::from(167409079868000u64)
+        );
+    }
+    #[test]
+    fn compile_errors() {
+        let err = |src: &[u8]| input_gen(src).unwrap_err().to_string();
+        assert_eq!(
+            err(b"in{x<5:foo,A}\n\n{x=1,m=1,a=1,s=1}"),
+            "workflow in forwards to unknown workflow foo"
+        );
+        assert_eq!(
+            err(b"in{x<5:R,A}\nfoo{A}\n\n{x=1,m=1,a=1,s=1}"),
+            "unreachable workflows: foo"
+        );
+        assert_eq!(
+            err(b"in{x<5:a,A}\na{b}\nb{m>3:in,R}\n\n{x=1,m=1,a=1,s=1}"),
+            "workflows in -> a -> b form a cycle"
+        );
+        assert_eq!(
+            err(b"px{A}\n\n{x=1,m=1,a=1,s=1}"),
+            "there is no \"in\" workflow"
+        );
+    }
+    #[test]
+    fn explain_example() {
+        let sys = input_gen(EXAMPLE).unwrap();
+        let d = sys.program.explain(&sys.parts[0]);
+        assert!(d.accepted);
+        assert_eq!(join_path(&d.path), "in:default qqz:0 qs:default lnx:0");
+        let d = sys.program.explain(&sys.parts[1]);
+        assert!(!d.accepted);
+        assert_eq!(join_path(&d.path), "in:0 px:default rfg:0 gd:default");
+    }
+    #[test]
+    fn regions_example() {
+        let sys = input_gen(EXAMPLE).unwrap();
+        let regions = sys.accepted_regions();
+        for p in &sys.parts {
+            let inside = regions
+                .iter()
+                .filter(|r| {
+                    r.probe
+                        .scores
+                        .iter()
+                        .zip(&p.scores)
+                        .all(|(c, &v)| c.min <= v && v <= c.max)
+                })
+                .collect::<Vec<_>>();
+            let d = sys.program.explain(p);
+            assert_eq!(inside.len(), d.accepted as usize);
+            if let [r] = inside[..] {
+                assert_eq!(r.path, d.path);
+            }
+        }
+        let csv = regions_csv(&sys.schema, &regions);
+        assert_eq!(csv.lines().count(), regions.len() + 1);
+        assert!(csv.starts_with("x_min,x_max,m_min,m_max,a_min,a_max,s_min,s_max,path\n"));
+        let json = regions_json(&sys.schema, &regions[..1]);
+        assert!(json.starts_with("[{\"x\":["));
+        assert!(json.ends_with("\"]}]"));
+    }
+    #[test]
+    fn custom_categories() {
+        let src = b"in{hue<3:A,size>7:R,ab}\nab{hue>5:A,R}\n\n{size=1,hue=2}\n{hue=9,size=9}";
+        let sys = System::parse(src).unwrap();
+        assert_eq!(sys.schema.names, ["size", "hue"]);
+        assert_eq!(sys.parts[1].scores, [9, 9]);
+        assert_eq!(part1(&sys), 3);
+
+        let schema: Schema = "hue=0..=9,size=0..=9".parse().unwrap();
+        let sys = System::parse_with(src, Some(schema)).unwrap();
+        assert_eq!(sys.parts[1].scores, [9, 9]);
+        // hue<3: 3 * 10, then hue>5 with size<=7: 4 * 8
+        assert_eq!(part2(&sys), BigUint::from(30u32 + 32));
+
+        let err = System::parse(b"in{q<3:A,R}\n\n{x=1}").unwrap_err();
+        assert_eq!(err.to_string(), "workflow in uses unknown category q");
+        let err = System::parse(b"in{A}\n\n{x=1}\n{y=1}").unwrap_err();
+        assert_eq!(err.to_string(), "part 2 has unknown category y");
+        let err = System::parse(b"in{A}\n\n{x=4001}").unwrap_err();
+        assert_eq!(err.to_string(), "part 1 has x=4001 outside 1..=4000");
+        let err = "x=0..=9,y=0..=9,x=1..=2".parse::<Schema>().unwrap_err();
+        assert_eq!(err.to_string(), "category x is defined more than once");
+    }
+    #[test]
+    fn export_escapes_names() {
+        let schema: Schema = r#"a"b=1..=2,c\d=1..=2,e f=1..=2"#.parse().unwrap();
+        let sys = System::parse_with(b"in{A}\n\n", Some(schema)).unwrap();
+        let regions = sys.accepted_regions();
+        assert_eq!(
+            regions_json(&sys.schema, &regions),
+            r#"[{"a\"b":[1,2],"c\\d":[1,2],"e f":[1,2],"path":["in:default"]}]"#
+        );
+        // commas can't come from a parsed schema
+        let domain = Constraints { min: 1, max: 2 };
+        let schema = Schema::new([("a\"b", domain), ("c,d", domain)]);
+        let sys = System::parse_with(b"in{A}\n\n", Some(schema)).unwrap();
+        assert_eq!(
+            regions_csv(&sys.schema, &sys.accepted_regions()),
+            "\"a\"\"b_min\",\"a\"\"b_max\",\"c,d_min\",\"c,d_max\",path\n1,2,1,2,in:default\n"
+        );
+    }
+    #[test]
+    fn count_does_not_overflow() {
+        let schema = Schema::new("abcdefgh".chars().map(|c| (c, Constraints::default())));
+        let sys = System::parse_with(b"in{A}\n\n", Some(schema)).unwrap();
+        assert_eq!(part2(&sys), BigUint::from(4000u32).pow(8));
+    }
+    #[test]
+    fn comparison_ops() {
+        let src = b"in{x<=2:lo,x>=9:A,x==5:A,R}\nlo{x!=0:A,R}\n\n{x=0}\n{x=2}\n{x=5}\n{x=9}";
+        let schema: Schema = "x=0..=9".parse().unwrap();
+        let sys = System::parse_with(src, Some(schema)).unwrap();
+        let accepted: Vec<_> = sys.parts.iter().map(|p| sys.program.accepts(p)).collect();
+        assert_eq!(accepted, [false, true, true, true]);
+        // 1, 2, 5 and 9
+        assert_eq!(part2(&sys), BigUint::from(4u32));
+    }
+
+    /// Random acyclic systems on tiny domains, checked against brute force.
+    #[test]
+    fn symbolic_matches_brute_force() {
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let ops = ["<", "<=", ">", ">=", "==", "!="];
+        let names = ["in", "wa", "wb", "wc", "wd", "we"];
+        for _ in 0..300 {
+            let n_categ = 1 + rand(3) as usize;
+            let domains: Vec<_> = (0..n_categ)
+                .map(|_| {
+                    let min = rand(3);
+                    Constraints {
+                        min,
+                        max: min + rand(4),
+                    }
+                })
+                .collect();
+            let schema = Schema::new(["x", "y", "z"].into_iter().zip(domains.clone()));
+            let n_workflows = 1 + rand(names.len() as u64) as usize;
+            let mut src = String::new();
+            for i in 0..n_workflows {
+                let later = (n_workflows - i - 1) as u64;
+                let dest = |r: u64, fwd: u64| match r {
+                    0 => "A",
+                    1 => "R",
+                    _ if later > 0 => names[i + 1 + (fwd % later) as usize],
+                    _ => "A",
+                };
+                src.push_str(names[i]);
+                src.push('{');
+                for _ in 0..rand(4) {
+                    let c = ["x", "y", "z"][rand(n_categ as u64) as usize];
+                    let op = ops[rand(ops.len() as u64) as usize];
+                    let thresh = rand(9);
+                    let d = dest(rand(3), rand(6));
+                    src.push_str(&format!("{c}{op}{thresh}:{d},"));
+                }
+                if i + 1 < n_workflows {
+                    // keeps every workflow reachable
+                    src.push_str(names[i + 1]);
+                } else {
+                    src.push_str(dest(rand(2), 0));
+                }
+                src.push_str("}\n");
+            }
+            src.push('\n');
+            let sys = System::parse_with(src.as_bytes(), Some(schema)).unwrap();
+            let regions = sys.accepted_regions();
+            let mut count = 0u32;
+            let mut scores = domains.iter().map(|d| d.min).collect::<Vec<_>>();
+            'all: loop {
+                let p = Part {
+                    scores: scores.clone(),
+                };
+                let d = sys.program.explain(&p);
+                let inside: Vec<_> = regions
+                    .iter()
+                    .filter(|r| {
+                        r.probe
+                            .scores
+                            .iter()
+                            .zip(&p.scores)
+                            .all(|(c, &v)| c.min <= v && v <= c.max)
+                    })
+                    .collect();
+                assert_eq!(inside.len(), d.accepted as usize, "{src} {p:?}");
+                if let [r] = inside[..] {
+                    assert_eq!(r.path, d.path, "{src} {p:?}");
+                    count += 1;
+                }
+                for (v, d) in scores.iter_mut().zip(&domains) {
+                    if *v < d.max {
+                        *v += 1;
+                        continue 'all;
+                    }
+                    *v = d.min;
+                }
+                break;
+            }
+            assert_eq!(part2(&sys), BigUint::from(count), "{src}");
+        }
     }
 }
@@ -1,9 +1,14 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use num::integer::{ExtendedGcd, Integer};
+use petgraph::algo::tarjan_scc;
+use petgraph::visit::Dfs;
 use petgraph::{graph::NodeIndex, Direction, Graph};
 use pom::parser::*;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::io::Write;
 
 fn ident<'a>() -> Parser<'a, u8, String> {
     one_of(b"abcdefghijklmnopqrstuvwxyz")
@@ -29,17 +34,17 @@ fn conf<'a>() -> Parser<'a, u8, Vec<Module>> {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum ModuleType {
+pub enum ModuleType {
     FlipFlop,
     Conjunction,
     None,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Module {
-    name: String,
-    ty: ModuleType,
-    succs: Vec<String>,
+pub struct Module {
+    pub name: String,
+    pub ty: ModuleType,
+    pub succs: Vec<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -74,110 +79,736 @@ impl ModuleState {
     }
 }
 
+pub fn parse_modules(input: &[u8]) -> Result<Vec<Module>> {
+    Ok(conf().parse(input)?)
+}
+
 #[aoc_generator(day20)]
 fn input_gen(input: &[u8]) -> Result<Vec<Module>> {
-    Ok(conf().parse(input)?)
+    parse_modules(input)
 }
 
-fn make_graph(input: &[Module]) -> (Graph<ModuleState, ()>, HashMap<String, NodeIndex>) {
-    let mut indices = HashMap::new();
-    let mut g: Graph<ModuleState, ()> = Graph::new();
-    for m in input {
-        indices.insert(
-            m.name.clone(),
-            g.add_node(ModuleState::new(m.name.clone(), m.ty)),
-        );
+/// A pulse sent from one module to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pulse {
+    pub from: NodeIndex,
+    pub to: NodeIndex,
+    pub high: bool,
+}
+
+/// Gets told about every pulse delivered by a `PulseNetwork`.
+pub trait Observer {
+    fn pulse(&mut self, net: &PulseNetwork, pulse: Pulse);
+    /// Called before the pulses of each button press, numbered from 1.
+    fn press(&mut self, _net: &PulseNetwork, _n: u64) {}
+}
+
+/// Counts low and high pulses.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub low: u64,
+    pub high: u64,
+}
+impl Observer for Counter {
+    fn pulse(&mut self, _net: &PulseNetwork, pulse: Pulse) {
+        if pulse.high {
+            self.high += 1;
+        } else {
+            self.low += 1;
+        }
+    }
+}
+
+/// Writes every pulse in the puzzle's `a -high-> b` notation, one per
+/// line, with a `# press N` line before each button press.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    pub error: Option<std::io::Error>,
+}
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        TraceWriter { out, error: None }
     }
-    for m in input {
-        let m_idx = indices[&m.name];
-        for s in &m.succs {
-            let s_idx = *indices
-                .entry(s.clone())
-                .or_insert_with(|| g.add_node(ModuleState::new(s.clone(), ModuleType::None)));
-            g.add_edge(m_idx, s_idx, ());
-            g[s_idx].state.push(false);
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+    fn write(&mut self, args: std::fmt::Arguments) {
+        if self.error.is_none() {
+            self.error = self.out.write_fmt(args).err();
         }
     }
-    (g, indices)
 }
-fn push_button(
-    g: &mut Graph<ModuleState, ()>,
+impl<W: Write> Observer for TraceWriter<W> {
+    fn pulse(&mut self, net: &PulseNetwork, pulse: Pulse) {
+        let level = if pulse.high { "high" } else { "low" };
+        self.write(format_args!(
+            "{} -{}-> {}\n",
+            net.name(pulse.from),
+            level,
+            net.name(pulse.to)
+        ));
+    }
+    fn press(&mut self, _net: &PulseNetwork, n: u64) {
+        self.write(format_args!("# press {n}\n"));
+    }
+}
+
+/// The memory of every module plus the pulses still in flight.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    presses: u64,
+    states: Vec<Vec<bool>>,
+    pending: Vec<(Pulse, usize)>,
+}
+
+/// A simulator for a module configuration, with a `button` module wired
+/// to `broadcaster`.
+#[derive(Clone, Debug)]
+pub struct PulseNetwork {
+    graph: Graph<ModuleState, ()>,
+    indices: HashMap<String, NodeIndex>,
+    button: NodeIndex,
     broadcaster: NodeIndex,
-    end: Option<NodeIndex>,
-) -> (u64, u64, Option<bool>) {
-    let mut queue = VecDeque::new();
-    queue.push_back((broadcaster, false, 0));
-    let mut high = 0;
-    let mut low = 0;
-    let mut end_pulse = None;
-    while let Some((cur, in_pulse, from_idx)) = queue.pop_front() {
-        if in_pulse {
-            high += 1;
-        } else {
-            low += 1;
+    /// Pulses waiting to be delivered, with the input slot at the receiver.
+    queue: VecDeque<(Pulse, usize)>,
+    presses: u64,
+}
+
+impl PulseNetwork {
+    pub fn new(modules: &[Module]) -> Result<PulseNetwork> {
+        let mut indices = HashMap::new();
+        let mut graph: Graph<ModuleState, ()> = Graph::new();
+        for m in modules {
+            let idx = graph.add_node(ModuleState::new(m.name.clone(), m.ty));
+            if indices.insert(m.name.clone(), idx).is_some() {
+                bail!("module {} is defined more than once", m.name);
+            }
+        }
+        if indices.contains_key("button") {
+            bail!("the name \"button\" is reserved");
         }
-        if let Some(out_pulse) = g[cur].recv_pulse(from_idx, in_pulse) {
-            if Some(cur) == end && out_pulse {
-                end_pulse = Some(out_pulse);
-                break;
+        let Some(&broadcaster) = indices.get("broadcaster") else {
+            bail!("there is no broadcaster");
+        };
+        let button = graph.add_node(ModuleState::new("button".to_owned(), ModuleType::None));
+        indices.insert("button".to_owned(), button);
+        graph.add_edge(button, broadcaster, ());
+        graph[broadcaster].state.push(false);
+        for m in modules {
+            let m_idx = indices[&m.name];
+            for s in &m.succs {
+                let s_idx = *indices.entry(s.clone()).or_insert_with(|| {
+                    graph.add_node(ModuleState::new(s.clone(), ModuleType::None))
+                });
+                graph.add_edge(m_idx, s_idx, ());
+                graph[s_idx].state.push(false);
             }
-            let mut succs = g.neighbors_directed(cur, Direction::Outgoing).detach();
-            while let Some((_, s)) = succs.next(g) {
-                let (s_from_idx, _) = g
+        }
+        for n in graph.node_weights_mut() {
+            if n.ty == ModuleType::FlipFlop {
+                n.state = vec![false];
+            }
+        }
+        Ok(PulseNetwork {
+            graph,
+            indices,
+            button,
+            broadcaster,
+            queue: VecDeque::new(),
+            presses: 0,
+        })
+    }
+
+    pub fn index(&self, name: &str) -> Option<NodeIndex> {
+        self.indices.get(name).copied()
+    }
+    pub fn name(&self, n: NodeIndex) -> &str {
+        &self.graph[n].name
+    }
+    pub fn module_type(&self, n: NodeIndex) -> ModuleType {
+        self.graph[n].ty
+    }
+    pub fn graph(&self) -> Graph<&str, ()> {
+        self.graph.map(|_, m| m.name.as_str(), |_, _| ())
+    }
+    pub fn presses(&self) -> u64 {
+        self.presses
+    }
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues the low pulse from the button to the broadcaster.
+    pub fn press(&mut self) {
+        self.presses += 1;
+        let pulse = Pulse {
+            from: self.button,
+            to: self.broadcaster,
+            high: false,
+        };
+        self.queue.push_back((pulse, 0));
+    }
+
+    /// Delivers the next pending pulse and queues the ones it causes.
+    pub fn step(&mut self) -> Option<Pulse> {
+        let (pulse, slot) = self.queue.pop_front()?;
+        let cur = pulse.to;
+        if let Some(high) = self.graph[cur].recv_pulse(slot, pulse.high) {
+            // petgraph lists the most recently added edge first
+            let succs: Vec<_> = self
+                .graph
+                .neighbors_directed(cur, Direction::Outgoing)
+                .collect();
+            for &s in succs.iter().rev() {
+                let (s_slot, _) = self
+                    .graph
                     .neighbors_directed(s, Direction::Incoming)
                     .enumerate()
                     .find(|&(_, p)| p == cur)
                     .unwrap();
-                queue.push_back((s, out_pulse, s_from_idx));
+                self.queue.push_back((
+                    Pulse {
+                        from: cur,
+                        to: s,
+                        high,
+                    },
+                    s_slot,
+                ));
             }
         }
+        Some(pulse)
+    }
+
+    /// Presses the button and runs until no pulses are left.
+    pub fn push_button(&mut self, observer: &mut impl Observer) {
+        self.press();
+        observer.press(self, self.presses);
+        while let Some(pulse) = self.step() {
+            observer.pulse(self, pulse);
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            presses: self.presses,
+            states: self.graph.node_weights().map(|n| n.state.clone()).collect(),
+            pending: self.queue.iter().copied().collect(),
+        }
+    }
+
+    /// Restores a snapshot taken from this network or one built from the
+    /// same configuration.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let fits = snapshot.states.len() == self.graph.node_count()
+            && self
+                .graph
+                .node_weights()
+                .zip(&snapshot.states)
+                .all(|(n, s)| n.state.len() == s.len());
+        if !fits {
+            bail!("snapshot is from a different network");
+        }
+        for (n, s) in self.graph.node_weights_mut().zip(&snapshot.states) {
+            n.state.clone_from(s);
+        }
+        self.queue = snapshot.pending.iter().copied().collect();
+        self.presses = snapshot.presses;
+        Ok(())
+    }
+}
+
+/// `PulseNetwork` flattened into arrays: successor lists with the input
+/// slot each edge feeds already resolved, and all flip-flop bits and
+/// conjunction memories packed in one bitset. Modules keep the ids of the
+/// `NodeIndex`es they were compiled from.
+#[derive(Clone, Debug)]
+pub struct CompiledNetwork {
+    types: Vec<ModuleType>,
+    /// The outgoing edges of module `i` are `first_edge[i]..first_edge[i + 1]`.
+    first_edge: Vec<u32>,
+    /// Receiver and memory bit of each edge.
+    edges: Vec<(u32, u32)>,
+    /// First memory bit of each module.
+    mem_offset: Vec<u32>,
+    /// For conjunctions, how many inputs they have and how many remember high.
+    n_inputs: Vec<u32>,
+    high_inputs: Vec<u32>,
+    memory: Vec<u64>,
+    button: u32,
+    button_edge: u32,
+    /// Pulses as `(sender, edge, high)`, reused across presses.
+    queue: Vec<(u32, u32, bool)>,
+}
+
+impl PulseNetwork {
+    /// Compiles the network with its current memory. Pulses still in
+    /// flight are not carried over.
+    pub fn compile(&self) -> CompiledNetwork {
+        let g = &self.graph;
+        let n = g.node_count();
+        let mut mem_offset = Vec::with_capacity(n);
+        let mut bits = 0;
+        for m in g.node_weights() {
+            mem_offset.push(bits);
+            bits += m.state.len() as u32;
+        }
+        let mut memory = vec![0u64; (bits as usize).div_ceil(64)];
+        let mut high_inputs = vec![0; n];
+        for (i, m) in g.node_weights().enumerate() {
+            for (slot, &v) in m.state.iter().enumerate() {
+                if v {
+                    let bit = mem_offset[i] as usize + slot;
+                    memory[bit / 64] |= 1 << (bit % 64);
+                    high_inputs[i] += 1;
+                }
+            }
+        }
+        let mut first_edge = Vec::with_capacity(n + 1);
+        let mut edges = vec![];
+        let mut button_edge = 0;
+        for cur in g.node_indices() {
+            first_edge.push(edges.len() as u32);
+            let succs: Vec<_> = g.neighbors_directed(cur, Direction::Outgoing).collect();
+            for &s in succs.iter().rev() {
+                let slot = g
+                    .neighbors_directed(s, Direction::Incoming)
+                    .position(|p| p == cur)
+                    .unwrap();
+                if cur == self.button {
+                    button_edge = edges.len() as u32;
+                }
+                edges.push((s.index() as u32, mem_offset[s.index()] + slot as u32));
+            }
+        }
+        first_edge.push(edges.len() as u32);
+        CompiledNetwork {
+            types: g.node_weights().map(|m| m.ty).collect(),
+            first_edge,
+            edges,
+            mem_offset,
+            n_inputs: g.node_weights().map(|m| m.state.len() as u32).collect(),
+            high_inputs,
+            memory,
+            button: self.button.index() as u32,
+            button_edge,
+            queue: vec![],
+        }
     }
-    (low, high, end_pulse)
 }
 
-fn find_period(g: &mut Graph<ModuleState, ()>, start: NodeIndex, end: NodeIndex) -> u64 {
-    let mut i = 1;
-    while push_button(g, start, Some(end)).2 != Some(true) {
-        i += 1;
+impl CompiledNetwork {
+    fn bit(&self, bit: u32) -> bool {
+        self.memory[bit as usize / 64] & (1 << (bit % 64)) != 0
+    }
+    fn flip(&mut self, bit: u32) {
+        self.memory[bit as usize / 64] ^= 1 << (bit % 64);
+    }
+
+    /// Presses the button, calling `f(from, to, high)` for every pulse in
+    /// the order they are delivered.
+    pub fn push_button_with(&mut self, mut f: impl FnMut(usize, usize, bool)) {
+        let mut queue = std::mem::take(&mut self.queue);
+        queue.clear();
+        queue.push((self.button, self.button_edge, false));
+        let mut head = 0;
+        while head < queue.len() {
+            let (from, edge, high) = queue[head];
+            head += 1;
+            let (to, bit) = self.edges[edge as usize];
+            f(from as usize, to as usize, high);
+            let out = match self.types[to as usize] {
+                ModuleType::FlipFlop => {
+                    if high {
+                        continue;
+                    }
+                    let bit = self.mem_offset[to as usize];
+                    self.flip(bit);
+                    self.bit(bit)
+                }
+                ModuleType::Conjunction => {
+                    if self.bit(bit) != high {
+                        self.flip(bit);
+                        if high {
+                            self.high_inputs[to as usize] += 1;
+                        } else {
+                            self.high_inputs[to as usize] -= 1;
+                        }
+                    }
+                    self.high_inputs[to as usize] != self.n_inputs[to as usize]
+                }
+                ModuleType::None => high,
+            };
+            let succs = self.first_edge[to as usize]..self.first_edge[to as usize + 1];
+            queue.extend(succs.map(|e| (to, e, out)));
+        }
+        self.queue = queue;
+    }
+
+    /// Presses the button and counts the low and high pulses sent.
+    pub fn push_button(&mut self) -> Counter {
+        let mut counter = Counter::default();
+        self.push_button_with(|_, _, high| {
+            if high {
+                counter.high += 1;
+            } else {
+                counter.low += 1;
+            }
+        });
+        counter
     }
-    i
+}
+
+/// Modules reachable from only one of the broadcaster's outputs. Nothing
+/// outside of it but the broadcaster can send pulses into it, so its
+/// state evolves independently from the rest of the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subcircuit {
+    /// The broadcaster output driving it.
+    pub input: NodeIndex,
+    /// Its strongly connected components.
+    pub sccs: Vec<Vec<NodeIndex>>,
+}
+impl Subcircuit {
+    pub fn modules(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.sccs.iter().flatten().copied()
+    }
+    pub fn contains(&self, n: NodeIndex) -> bool {
+        self.modules().any(|m| m == n)
+    }
+}
+
+/// When a module inside a sub-circuit sends high pulses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    /// Presses before the sub-circuit's state starts repeating.
+    pub pre_period: u64,
+    pub period: u64,
+    /// The presses, up to `pre_period + period`, during which the module
+    /// sends at least one high pulse. Presses are numbered from 1.
+    pub hits: Vec<u64>,
+}
+impl Cycle {
+    fn hits_at(&self, press: u64) -> bool {
+        let p = if press > self.pre_period + self.period {
+            (press - self.pre_period - 1) % self.period + self.pre_period + 1
+        } else {
+            press
+        };
+        self.hits.contains(&p)
+    }
+}
+
+impl PulseNetwork {
+    pub fn subcircuits(&self) -> Vec<Subcircuit> {
+        let g = &self.graph;
+        // petgraph lists the most recently added edge first
+        let mut inputs: Vec<_> = g
+            .neighbors_directed(self.broadcaster, Direction::Outgoing)
+            .collect();
+        inputs.reverse();
+        let mut reached_by = vec![vec![]; g.node_count()];
+        for (i, &input) in inputs.iter().enumerate() {
+            let mut dfs = Dfs::new(g, input);
+            while let Some(n) = dfs.next(g) {
+                if n != self.broadcaster {
+                    reached_by[n.index()].push(i);
+                }
+            }
+        }
+        let mut subs: Vec<_> = inputs
+            .iter()
+            .map(|&input| Subcircuit {
+                input,
+                sccs: vec![],
+            })
+            .collect();
+        for scc in tarjan_scc(g) {
+            // every module of a component is reached from the same outputs
+            if let [i] = reached_by[scc[0].index()][..] {
+                subs[i].sccs.push(scc);
+            }
+        }
+        subs.retain(|s| !s.sccs.is_empty());
+        subs
+    }
+}
+
+impl PulseNetwork {
+    /// Renders the configuration for Graphviz, drawing flip-flops as boxes,
+    /// conjunctions as inverted houses, the broadcaster as a diamond and
+    /// modules without a type as sinks. Each sub-circuit gets a cluster.
+    ///
+    /// With `Some(n)`, the network is first pressed `n` more times on a copy
+    /// and the nodes are filled by their memory: flip-flops that are on and
+    /// conjunctions remembering a high pulse from every input in gold, other
+    /// conjunctions remembering some high pulses in light yellow.
+    pub fn to_dot(&self, presses: Option<u64>) -> String {
+        use std::fmt::Write;
+
+        let mut net = self.clone();
+        for _ in 0..presses.unwrap_or(0) {
+            net.push_button(&mut Counter::default());
+        }
+        let g = &net.graph;
+        let node = |n: NodeIndex| {
+            let m = &g[n];
+            let shape = match m.ty {
+                ModuleType::FlipFlop => "box",
+                ModuleType::Conjunction => "invhouse",
+                ModuleType::None if n == net.broadcaster => "diamond",
+                ModuleType::None => "doublecircle",
+            };
+            let mut attrs = format!("shape = {shape}");
+            if presses.is_some() && m.ty != ModuleType::None {
+                let high = m.state.iter().filter(|&&b| b).count();
+                let fill = if high == 0 {
+                    "white"
+                } else if high == m.state.len() {
+                    "gold"
+                } else {
+                    "lightyellow"
+                };
+                write!(attrs, ", style = filled, fillcolor = {fill}").unwrap();
+            }
+            format!("{:?} [ {attrs} ]", m.name)
+        };
+        let mut out = String::from("digraph {\n");
+        let mut clustered = vec![false; g.node_count()];
+        for (i, sub) in net.subcircuits().iter().enumerate() {
+            writeln!(out, "    subgraph cluster_{i} {{").unwrap();
+            writeln!(out, "        label = {:?}", g[sub.input].name).unwrap();
+            let mut modules: Vec<_> = sub.modules().collect();
+            modules.sort();
+            for n in modules {
+                clustered[n.index()] = true;
+                writeln!(out, "        {}", node(n)).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        for n in g.node_indices() {
+            if !clustered[n.index()] && n != net.button {
+                writeln!(out, "    {}", node(n)).unwrap();
+            }
+        }
+        for n in g.node_indices().filter(|&n| n != net.button) {
+            let succs: Vec<_> = g.neighbors_directed(n, Direction::Outgoing).collect();
+            for &s in succs.iter().rev() {
+                writeln!(out, "    {:?} -> {:?}", g[n].name, g[s].name).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl CompiledNetwork {
+    /// Presses the button until the memory of `modules` repeats, tracking
+    /// when `watch` sends high pulses. Gives up after `limit` presses.
+    pub fn find_cycle(&self, modules: &[NodeIndex], watch: NodeIndex, limit: u64) -> Option<Cycle> {
+        let mut net = self.clone();
+        let key = |net: &CompiledNetwork| -> Vec<bool> {
+            modules
+                .iter()
+                .flat_map(|m| {
+                    let start = net.mem_offset[m.index()];
+                    (start..start + net.n_inputs[m.index()]).map(|b| net.bit(b))
+                })
+                .collect()
+        };
+        let mut seen = HashMap::new();
+        seen.insert(key(&net), 0);
+        let mut hits = vec![];
+        for press in 1..=limit {
+            let mut hit = false;
+            net.push_button_with(|from, _, high| hit |= high && from == watch.index());
+            if hit {
+                hits.push(press);
+            }
+            match seen.entry(key(&net)) {
+                Entry::Occupied(e) => {
+                    let first = *e.get();
+                    return Some(Cycle {
+                        pre_period: first,
+                        period: press - first,
+                        hits,
+                    });
+                }
+                Entry::Vacant(e) => {
+                    e.insert(press);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Solves `x = a1 (mod m1)`, `x = a2 (mod m2)` for moduli that need not be
+/// coprime, returning `x` modulo the lcm.
+fn crt(a1: i128, m1: i128, a2: i128, m2: i128) -> Option<(i128, i128)> {
+    let ExtendedGcd { gcd: g, x: p, .. } = m1.extended_gcd(&m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let l = m1 / g * m2;
+    let k = ((a2 - a1) / g * p).rem_euclid(m2 / g);
+    Some(((a1 + m1 * k).rem_euclid(l), l))
+}
+
+/// Combinations of hit residues the analysis is willing to go through.
+const MAX_COMBINATIONS: usize = 1 << 16;
+
+/// The first press during which every cycle has a hit, if there is one.
+///
+/// Past the pre-periods, every combination of one repeating hit per cycle
+/// is a candidate, so this gives up when their number could exceed
+/// [`MAX_COMBINATIONS`].
+fn first_common_hit(cycles: &[Cycle]) -> Result<Option<u64>> {
+    let Some(max_pre) = cycles.iter().map(|c| c.pre_period).max() else {
+        return Ok(None);
+    };
+    if let Some(p) = (1..=max_pre).find(|&p| cycles.iter().all(|c| c.hits_at(p))) {
+        return Ok(Some(p));
+    }
+    // past every pre-period each cycle hits on fixed residues
+    let residues = |c: &Cycle| c.hits.iter().filter(|&&h| h > c.pre_period).count();
+    let combinations = cycles
+        .iter()
+        .try_fold(1usize, |n, c| n.checked_mul(residues(c).max(1)));
+    if combinations.is_none_or(|n| n > MAX_COMBINATIONS) {
+        bail!(
+            "the cycles hit during too many presses to combine: {}",
+            cycles
+                .iter()
+                .map(|c| format!("{} of every {}", residues(c), c.period))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let mut solutions = vec![(0i128, 1i128)];
+    for c in cycles {
+        let residues = c.hits.iter().filter(|&&h| h > c.pre_period);
+        let period = c.period as i128;
+        solutions = solutions
+            .iter()
+            .flat_map(|&(a, m)| {
+                residues
+                    .clone()
+                    .filter_map(move |&r| crt(a, m, r as i128, period))
+            })
+            .collect();
+        // every solution is modulo the same lcm by now
+        solutions.sort_unstable();
+        solutions.dedup();
+    }
+    let from = max_pre as i128 + 1;
+    Ok(solutions
+        .into_iter()
+        .map(|(a, m)| from + (a - from).rem_euclid(m))
+        .min()
+        .and_then(|p| u64::try_from(p).ok()))
+}
+
+/// Presses that are simulated directly before falling back to the analysis.
+const SIMULATED_PRESSES: u64 = 10_000;
+/// Presses after which a sub-circuit is given up on if it hasn't repeated.
+const CYCLE_LIMIT: u64 = 1 << 20;
+
+/// The fewest button presses after which `target` receives a low pulse.
+///
+/// Tries a plain simulation first. After that, `target` has to be fed by a
+/// single conjunction whose inputs each sit in a different sub-circuit;
+/// the conjunction is assumed to send a low pulse during any press in which
+/// all of its inputs send a high one. Each sub-circuit's pre-period and
+/// period are found by hashing its state, and the presses when all the
+/// inputs fire together are combined with the Chinese remainder theorem.
+pub fn presses_until_low(net: &PulseNetwork, target: &str, simulated: u64) -> Result<u64> {
+    let Some(rx) = net.index(target) else {
+        bail!("there is no module called {target}");
+    };
+    let compiled = net.compile();
+    let mut sim = compiled.clone();
+    for press in 1..=simulated {
+        let mut hit = false;
+        sim.push_button_with(|_, to, high| hit |= !high && to == rx.index());
+        if hit {
+            return Ok(press);
+        }
+    }
+    let g = &net.graph;
+    let feeders: Vec<_> = g.neighbors_directed(rx, Direction::Incoming).collect();
+    let [feeder] = feeders[..] else {
+        bail!(
+            "{} is fed by {} modules, the analysis needs exactly one conjunction",
+            target,
+            feeders.len()
+        );
+    };
+    if g[feeder].ty != ModuleType::Conjunction {
+        bail!(
+            "{} is fed by {}, which is not a conjunction",
+            target,
+            g[feeder].name
+        );
+    }
+    let subs = net.subcircuits();
+    let mut used = vec![];
+    let mut cycles = vec![];
+    for i in g.neighbors_directed(feeder, Direction::Incoming) {
+        let Some(sub) = subs.iter().position(|s| s.contains(i)) else {
+            bail!(
+                "{} feeds {} but is reachable from several broadcaster outputs",
+                g[i].name,
+                g[feeder].name
+            );
+        };
+        if used.contains(&sub) {
+            bail!(
+                "several inputs of {} come from the sub-circuit driven by {}",
+                g[feeder].name,
+                g[subs[sub].input].name
+            );
+        }
+        used.push(sub);
+        let modules: Vec<_> = subs[sub].modules().collect();
+        let Some(cycle) = compiled.find_cycle(&modules, i, CYCLE_LIMIT) else {
+            bail!(
+                "the sub-circuit driven by {} doesn't repeat within {} presses",
+                g[subs[sub].input].name,
+                CYCLE_LIMIT
+            );
+        };
+        if cycle.hits.is_empty() {
+            bail!("{} never sends a high pulse", g[i].name);
+        }
+        cycles.push(cycle);
+    }
+    first_common_hit(&cycles)
+        .with_context(|| format!("can't line up the inputs of {}", g[feeder].name))?
+        .ok_or_else(|| {
+            anyhow!(
+                "the inputs of {} never all send a high pulse during the same press",
+                g[feeder].name
+            )
+        })
 }
 
 #[aoc(day20, part1)]
-fn part1(input: &[Module]) -> u64 {
-    let (mut g, indices) = make_graph(input);
-    let broadcaster = indices["broadcaster"];
+fn part1(input: &[Module]) -> Result<u64> {
+    let mut net = PulseNetwork::new(input)?.compile();
     let mut low = 0;
     let mut high = 0;
     for _ in 0..1000 {
-        let (l, h, _) = push_button(&mut g, broadcaster, None);
-        low += l;
-        high += h;
+        let c = net.push_button();
+        low += c.low;
+        high += c.high;
     }
-    low * high
+    Ok(low * high)
 }
 
 #[aoc(day20, part2)]
-fn part2(input: &[Module]) -> u64 {
-    let (g, indices) = make_graph(input);
-    let broadcaster = indices["broadcaster"];
-    let rx = indices["rx"];
-    let pred = g
-        .neighbors_directed(rx, Direction::Incoming)
-        .next()
-        .unwrap();
-    assert!(
-        g[pred].ty == ModuleType::Conjunction,
-        "unexpected graph shape"
-    );
-    let incoming: Vec<_> = g.neighbors_directed(pred, Direction::Incoming).collect();
-    let mut ret = 1;
-    for i in incoming {
-        let mut subg = g.clone();
-        let p = find_period(&mut subg, broadcaster, i);
-        ret = num::integer::lcm(ret, p);
-    }
-    ret
+fn part2(input: &[Module]) -> Result<u64> {
+    let net = PulseNetwork::new(input)?;
+    presses_until_low(&net, "rx", SIMULATED_PRESSES)
 }
 
 #[cfg(test)]
@@ -197,12 +828,237 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(part1(&input_gen(EXAMPLE).unwrap()), 32000000);
+        assert_eq!(part1(&input_gen(EXAMPLE).unwrap()).unwrap(), 32000000);
     }
     #[test]
     fn part1_example2() {
-        assert_eq!(part1(&input_gen(EXAMPLE2).unwrap()), 11687500);
+        assert_eq!(part1(&input_gen(EXAMPLE2).unwrap()).unwrap(), 11687500);
+    }
+    #[test]
+    fn part1_invalid() {
+        let err = |src: &[u8]| part1(&input_gen(src).unwrap()).unwrap_err().to_string();
+        assert_eq!(err(b"%a -> b"), "there is no broadcaster");
+        assert_eq!(
+            err(b"broadcaster -> a\n%a -> b\n&a -> b"),
+            "module a is defined more than once"
+        );
     }
     #[test]
     fn part2_example() {}
+
+    // `ia` sends high every 2nd press and `iy` every 4th one
+    const COUNTERS: &[u8] = br#"broadcaster -> a, x
+%a -> ia
+&ia -> out
+%x -> y
+%y -> iy
+&iy -> out
+&out -> rx"#;
+
+    #[test]
+    fn counters() {
+        let net = PulseNetwork::new(&input_gen(COUNTERS).unwrap()).unwrap();
+        let subs = net.subcircuits();
+        let names = |s: &Subcircuit| {
+            let mut v: Vec<_> = s.modules().map(|m| net.name(m)).collect();
+            v.sort();
+            v
+        };
+        assert_eq!(names(&subs[0]), ["a", "ia"]);
+        assert_eq!(names(&subs[1]), ["iy", "x", "y"]);
+        let cycle = net
+            .compile()
+            .find_cycle(
+                &subs[1].modules().collect::<Vec<_>>(),
+                net.index("iy").unwrap(),
+                100,
+            )
+            .unwrap();
+        assert_eq!(
+            cycle,
+            Cycle {
+                pre_period: 0,
+                period: 4,
+                hits: vec![4]
+            }
+        );
+        assert_eq!(presses_until_low(&net, "rx", 100).unwrap(), 4);
+        assert_eq!(presses_until_low(&net, "rx", 0).unwrap(), 4);
+    }
+    #[test]
+    fn offsets() {
+        let c = |pre_period, period, hits: &[u64]| Cycle {
+            pre_period,
+            period,
+            hits: hits.to_vec(),
+        };
+        assert_eq!(
+            first_common_hit(&[c(0, 4, &[3]), c(2, 6, &[5])]).unwrap(),
+            Some(11)
+        );
+        assert_eq!(
+            first_common_hit(&[c(0, 4, &[2]), c(2, 6, &[5])]).unwrap(),
+            None
+        );
+        assert_eq!(
+            first_common_hit(&[c(0, 4, &[1]), c(3, 6, &[1, 7])]).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            first_common_hit(&[c(0, 4, &[2, 4]), c(3, 5, &[4, 8])]).unwrap(),
+            Some(4)
+        );
+        assert_eq!(
+            first_common_hit(&[c(0, 3, &[3]), c(0, 5, &[5]), c(0, 7, &[7])]).unwrap(),
+            Some(105)
+        );
+        let every = |period| c(0, period, &(1..=period).collect::<Vec<_>>());
+        assert_eq!(
+            first_common_hit(&[every(1000), every(1000)])
+                .unwrap_err()
+                .to_string(),
+            "the cycles hit during too many presses to combine: 1000 of every 1000, 1000 of every 1000"
+        );
+        // duplicate combinations are merged as they're found
+        assert_eq!(
+            first_common_hit(&[every(200), every(100), c(0, 7, &[7])]).unwrap(),
+            Some(7)
+        );
+    }
+    #[test]
+    fn explanations() {
+        let err = |src: &[u8]| {
+            let net = PulseNetwork::new(&input_gen(src).unwrap()).unwrap();
+            presses_until_low(&net, "rx", 0).unwrap_err().to_string()
+        };
+        assert_eq!(
+            err(b"broadcaster -> a, b\n%a -> rx\n%b -> rx"),
+            "rx is fed by 2 modules, the analysis needs exactly one conjunction"
+        );
+        assert_eq!(
+            err(b"broadcaster -> i\n&i -> a\n&a -> b\n&b -> rx"),
+            "a never sends a high pulse"
+        );
+        assert_eq!(
+            err(b"broadcaster -> a, b\n%a -> c\n%b -> c\n&c -> d\n&d -> rx"),
+            "c feeds d but is reachable from several broadcaster outputs"
+        );
+    }
+    #[test]
+    fn dot_export() {
+        let mut net = PulseNetwork::new(&input_gen(EXAMPLE2).unwrap()).unwrap();
+        assert_eq!(
+            net.to_dot(None),
+            r#"digraph {
+    subgraph cluster_0 {
+        label = "a"
+        "a" [ shape = box ]
+        "inv" [ shape = invhouse ]
+        "b" [ shape = box ]
+        "con" [ shape = invhouse ]
+        "output" [ shape = doublecircle ]
+    }
+    "broadcaster" [ shape = diamond ]
+    "broadcaster" -> "a"
+    "a" -> "inv"
+    "a" -> "con"
+    "inv" -> "b"
+    "b" -> "con"
+    "con" -> "output"
+}
+"#
+        );
+        let dot = net.to_dot(Some(1));
+        assert!(dot.contains(r#""a" [ shape = box, style = filled, fillcolor = gold ]"#));
+        assert!(dot.contains(r#""con" [ shape = invhouse, style = filled, fillcolor = gold ]"#));
+        assert!(dot.contains(r#""b" [ shape = box, style = filled, fillcolor = gold ]"#));
+        // exporting doesn't disturb the network itself
+        net.push_button(&mut Counter::default());
+        assert_eq!(net.to_dot(Some(0)), dot);
+    }
+    #[test]
+    fn trace_example() {
+        let mut net = PulseNetwork::new(&input_gen(EXAMPLE).unwrap()).unwrap();
+        let mut trace = TraceWriter::new(vec![]);
+        net.push_button(&mut trace);
+        assert!(trace.error.is_none());
+        assert_eq!(
+            String::from_utf8(trace.into_inner()).unwrap(),
+            "\
+# press 1
+button -low-> broadcaster
+broadcaster -low-> a
+broadcaster -low-> b
+broadcaster -low-> c
+a -high-> b
+b -high-> c
+c -high-> inv
+inv -low-> a
+a -low-> b
+b -low-> c
+c -low-> inv
+inv -high-> a
+"
+        );
+    }
+    #[test]
+    fn step_by_step() {
+        let mut net = PulseNetwork::new(&input_gen(EXAMPLE2).unwrap()).unwrap();
+        net.press();
+        let first = net.step().unwrap();
+        assert_eq!(net.name(first.from), "button");
+        let second = net.step().unwrap();
+        assert_eq!(net.name(second.to), "a");
+        assert!(!second.high);
+        let rest: Vec<_> = std::iter::from_fn(|| net.step()).collect();
+        assert_eq!(rest.len(), 6);
+        assert!(net.is_idle());
+    }
+    #[test]
+    fn compiled_matches_simulator() {
+        struct Log(Vec<(usize, usize, bool)>);
+        impl Observer for Log {
+            fn pulse(&mut self, _net: &PulseNetwork, p: Pulse) {
+                self.0.push((p.from.index(), p.to.index(), p.high));
+            }
+        }
+        for example in [EXAMPLE, EXAMPLE2] {
+            let mut net = PulseNetwork::new(&input_gen(example).unwrap()).unwrap();
+            net.push_button(&mut Counter::default());
+            let mut compiled = net.compile();
+            for _ in 0..20 {
+                let mut expected = Log(vec![]);
+                net.push_button(&mut expected);
+                let mut got = vec![];
+                compiled.push_button_with(|from, to, high| got.push((from, to, high)));
+                assert_eq!(got, expected.0);
+            }
+        }
+    }
+    #[test]
+    fn snapshot_restore() {
+        let modules = input_gen(EXAMPLE2).unwrap();
+        let mut net = PulseNetwork::new(&modules).unwrap();
+        net.push_button(&mut Counter::default());
+        net.press();
+        net.step();
+        let snap = net.snapshot();
+        let run = |net: &mut PulseNetwork| {
+            let mut trace = TraceWriter::new(vec![]);
+            while let Some(p) = net.step() {
+                trace.pulse(net, p);
+            }
+            for _ in 0..3 {
+                net.push_button(&mut trace);
+            }
+            trace.into_inner()
+        };
+        let expected = run(&mut net);
+        let mut other = PulseNetwork::new(&modules).unwrap();
+        other.restore(&snap).unwrap();
+        assert_eq!(other.presses(), 2);
+        assert_eq!(run(&mut other), expected);
+        let small = PulseNetwork::new(&input_gen(EXAMPLE).unwrap()).unwrap();
+        assert!(other.restore(&small.snapshot()).is_err());
+    }
 }
@@ -1,19 +1,15 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
-use ndarray::Array2;
-use pom::parser::*;
 use std::collections::HashSet;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'.').map(|_| Tile::Plot) | sym(b'#').map(|_| Tile::Rock) | sym(b'S').map(|_| Tile::Start)
-}
-
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
-}
-
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..) - end()
+fn tile(b: u8) -> Option<Tile> {
+    match b {
+        b'.' => Some(Tile::Plot),
+        b'#' => Some(Tile::Rock),
+        b'S' => Some(Tile::Start),
+        _ => None,
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -24,25 +20,11 @@ enum Tile {
 }
 
 #[aoc_generator(day21)]
-fn input_gen(input: &[u8]) -> Result<Array2<Tile>> {
-    let data = map().parse(input)?;
-    let shape = (data.len(), data[0].len());
-    let flat = data.into_iter().flatten().collect();
-    Ok(Array2::from_shape_vec(shape, flat)?)
-}
-
-fn rem((y, x): (i64, i64), h: usize, w: usize) -> (i64, i64) {
-    (y.rem_euclid(h as i64), x.rem_euclid(w as i64))
-}
-fn wrap((y, x): (i64, i64), h: usize, w: usize) -> (usize, usize) {
-    (
-        y.rem_euclid(h as i64) as usize,
-        x.rem_euclid(w as i64) as usize,
-    )
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
 }
 
-fn neighbours(map: &Array2<Tile>, p: (i64, i64)) -> impl Iterator<Item = (i64, i64)> + '_ {
-    let &[h, w] = map.shape() else { unreachable!() };
+fn neighbours(map: &Grid<Tile>, p: (i64, i64)) -> impl Iterator<Item = (i64, i64)> + '_ {
     [
         (p.0 - 1, p.1),
         (p.0 + 1, p.1),
@@ -50,15 +32,13 @@ fn neighbours(map: &Array2<Tile>, p: (i64, i64)) -> impl Iterator<Item = (i64, i
         (p.0, p.1 + 1),
     ]
     .into_iter()
-    .filter(move |&p| map[wrap(p, h, w)] != Tile::Rock)
+    .filter(move |&p| *map.at_wrapped(p) != Tile::Rock)
 }
 
-fn tiles_within_steps(map: &Array2<Tile>, steps: i64, infinite: bool) -> i64 {
-    let &[h, w] = map.shape() else { unreachable!() };
+fn tiles_within_steps(map: &Grid<Tile>, steps: i64, infinite: bool) -> i64 {
     let s = map
-        .indexed_iter()
-        .find(|(_, &t)| t == Tile::Start)
-        .map(|(p, _)| (p.0 as i64, p.1 as i64))
+        .position(|&t| t == Tile::Start)
+        .map(|p| (p.0 as i64, p.1 as i64))
         .unwrap();
     let mut visited = HashSet::new();
     let mut cur = vec![s];
@@ -68,7 +48,7 @@ fn tiles_within_steps(map: &Array2<Tile>, steps: i64, infinite: bool) -> i64 {
         let mut next = vec![];
         for c in cur {
             for n in neighbours(map, c) {
-                if !infinite && rem(c, h, w) != c {
+                if !infinite && !map.in_bounds(c) {
                     continue;
                 }
                 if visited.contains(&n) {
@@ -87,14 +67,12 @@ fn tiles_within_steps(map: &Array2<Tile>, steps: i64, infinite: bool) -> i64 {
 }
 
 #[aoc(day21, part1)]
-fn part1(input: &Array2<Tile>) -> i64 {
+fn part1(input: &Grid<Tile>) -> i64 {
     tiles_within_steps(input, 64, false)
 }
 #[aoc(day21, part2)]
-fn part2(input: &Array2<Tile>) -> i64 {
-    let &[h, w] = input.shape() else {
-        unreachable!()
-    };
+fn part2(input: &Grid<Tile>) -> i64 {
+    let (h, w) = (input.height(), input.width());
     assert!(h == w, "not a square");
     let steps = 26501365;
     let m = steps % h as i64;
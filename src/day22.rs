@@ -1,6 +1,8 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use ndarray::{s, Array3};
+use ndarray::{s, Array2};
+use petgraph::algo::dominators::simple_fast;
+use petgraph::Graph;
 use pom::parser::*;
 
 fn unsigned<'a>() -> Parser<'a, u8, usize> {
@@ -19,120 +21,188 @@ fn pos<'a>() -> Parser<'a, u8, Pos> {
 }
 
 fn brick<'a>() -> Parser<'a, u8, Brick> {
-    (pos() - sym(b'~') + pos() - (sym(b'\n').discard() | end()))
-        .map(|(from, to)| Brick { from, to })
+    (pos() - sym(b'~') + pos() - end()).map(|(from, to)| Brick { from, to })
 }
 
-fn bricks<'a>() -> Parser<'a, u8, Vec<Brick>> {
-    brick().repeat(1..) - end()
-}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-struct Pos {
-    x: usize,
-    y: usize,
-    z: usize,
-}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-struct Brick {
-    from: Pos,
-    to: Pos,
-}
-impl Brick {
-    fn falling(&self, map: &Array3<bool>) -> bool {
-        let base = map.slice(s![
-            self.from.x..=self.to.x,
-            self.from.y..=self.to.y,
-            self.from.z - 1
-        ]);
-        !base.iter().any(|b| *b)
-    }
-    fn blit(&self, map: &mut Array3<bool>, value: bool) {
-        let tiles = map.slice_mut(s![
-            self.from.x..=self.to.x,
-            self.from.y..=self.to.y,
-            self.from.z..=self.to.z
-        ]);
-        for t in tiles {
-            *t = value;
+/// Reads one brick per line, rejecting bricks that are reversed, dip to
+/// z=0, aren't straight or overlap another one, with their line numbers.
+///
+/// With `normalize`, reversed bricks have their ends swapped instead.
+pub fn parse_bricks(input: &[u8], normalize: bool) -> Result<Vec<Brick>> {
+    let input = input.strip_suffix(b"\n").unwrap_or(input);
+    let mut ret = vec![];
+    for (i, line) in input.split(|&b| b == b'\n').enumerate() {
+        let line_no = i + 1;
+        let mut br = brick()
+            .parse(line)
+            .with_context(|| format!("line {line_no}: expected x,y,z~x,y,z"))?;
+        let axes = [
+            ("x", &mut br.from.x, &mut br.to.x),
+            ("y", &mut br.from.y, &mut br.to.y),
+            ("z", &mut br.from.z, &mut br.to.z),
+        ];
+        let mut straight = 0;
+        for (axis, from, to) in axes {
+            if *from > *to {
+                if !normalize {
+                    bail!("line {line_no}: brick goes backwards along {axis}");
+                }
+                std::mem::swap(from, to);
+            }
+            straight += (from != to) as usize;
+        }
+        if straight > 1 {
+            bail!("line {line_no}: brick is not a straight line");
         }
+        if br.from.z == 0 {
+            bail!("line {line_no}: brick is at z=0, below the lowest level 1");
+        }
+        ret.push(br);
     }
-    fn fall(&mut self, map: &mut Array3<bool>) {
-        assert!(self.from.z > 0);
-        self.blit(map, false);
-        self.from.z -= 1;
-        self.to.z -= 1;
-        self.blit(map, true);
+    if let Some((a, b)) = overlap(&ret) {
+        bail!("bricks on lines {} and {} overlap", a + 1, b + 1);
     }
+    Ok(ret)
 }
 
-fn fall(bricks: &mut [Brick], map: &mut Array3<bool>) -> usize {
-    let mut ret = 0;
-    for br in bricks {
-        let mut fell = false;
-        while br.falling(map) {
-            br.fall(map);
-            fell = true;
-        }
-        if fell {
-            ret += 1;
+/// The first pair of overlapping bricks, if any.
+fn overlap(bricks: &[Brick]) -> Option<(usize, usize)> {
+    let mut order: Vec<_> = (0..bricks.len()).collect();
+    order.sort_by_key(|&i| bricks[i].from.z);
+    let meet = |a: usize, b: usize, c: usize, d: usize| a <= d && c <= b;
+    let mut ret: Option<(usize, usize)> = None;
+    for (k, &i) in order.iter().enumerate() {
+        let a = &bricks[i];
+        // only bricks starting below the top of this one can reach it
+        for &j in order[k + 1..]
+            .iter()
+            .take_while(|&&j| bricks[j].from.z <= a.to.z)
+        {
+            let b = &bricks[j];
+            if meet(a.from.x, a.to.x, b.from.x, b.to.x) && meet(a.from.y, a.to.y, b.from.y, b.to.y)
+            {
+                let pair = (i.min(j), i.max(j));
+                ret = Some(ret.map_or(pair, |r| r.min(pair)));
+            }
         }
     }
     ret
 }
 
-fn init(input: &[Brick]) -> (Vec<Brick>, Array3<bool>) {
-    let mut bricks: Vec<_> = input.to_vec();
-    bricks.sort_by_key(|b| b.from.z);
-    let max_x = input.iter().map(|b| b.to.x.max(b.from.x)).max().unwrap();
-    let max_y = input.iter().map(|b| b.to.y.max(b.from.y)).max().unwrap();
-    let max_z = input.iter().map(|b| b.to.z.max(b.from.z)).max().unwrap();
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Pos {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Brick {
+    pub from: Pos,
+    pub to: Pos,
+}
 
-    let mut map = Array3::from_shape_fn((max_x + 1, max_y + 1, max_z + 1), |(_, _, z)| z == 0);
+/// The bricks after they have all come to rest, and which ones rest on
+/// which. Bricks are numbered in the order they landed, lowest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    pub bricks: Vec<Brick>,
+    /// The bricks lying directly on each brick.
+    pub supports: Vec<Vec<usize>>,
+    /// The bricks each brick lies directly on, empty for the ground.
+    pub supported_by: Vec<Vec<usize>>,
+}
 
-    for br in &mut bricks {
-        br.blit(&mut map, true);
+impl Stack {
+    /// Lets the bricks fall, lowest first, recording what each one lands on.
+    ///
+    /// Only the top of each column is tracked, with the brick it belongs
+    /// to, so every brick drops straight onto the highest top under it.
+    pub fn settle(input: &[Brick]) -> Stack {
+        let mut bricks: Vec<_> = input.to_vec();
+        bricks.sort_by_key(|b| b.from.z);
+        let max_x = bricks.iter().map(|b| b.to.x).max().unwrap_or(0);
+        let max_y = bricks.iter().map(|b| b.to.y).max().unwrap_or(0);
+        // height of each column and the brick on top, if any
+        let mut tops = Array2::from_elem((max_x + 1, max_y + 1), (0, None));
+        let mut supports = vec![vec![]; bricks.len()];
+        let mut supported_by = vec![];
+        for (id, br) in bricks.iter_mut().enumerate() {
+            let mut footprint = tops.slice_mut(s![br.from.x..=br.to.x, br.from.y..=br.to.y]);
+            let rest = footprint.iter().map(|t| t.0).max().unwrap_or(0);
+            let mut under: Vec<usize> = footprint
+                .iter()
+                .filter(|t| t.0 == rest)
+                .filter_map(|t| t.1)
+                .collect();
+            under.sort_unstable();
+            under.dedup();
+            br.to.z = br.to.z - br.from.z + rest + 1;
+            br.from.z = rest + 1;
+            footprint.fill((br.to.z, Some(id)));
+            for &u in &under {
+                supports[u].push(id);
+            }
+            supported_by.push(under);
+        }
+        Stack {
+            bricks,
+            supports,
+            supported_by,
+        }
     }
 
-    fall(&mut bricks, &mut map);
+    /// How many other bricks would fall if each brick was disintegrated.
+    ///
+    /// A brick falls exactly when every chain of supports from the ground
+    /// up to it goes through the removed brick, that is when the removed
+    /// brick dominates it with the ground as the root. So the count is the
+    /// size of the brick's subtree in the dominator tree, minus itself.
+    pub fn chain_reactions(&self) -> Vec<usize> {
+        let n = self.bricks.len();
+        let mut g = Graph::<(), ()>::with_capacity(n + 1, n);
+        let nodes: Vec<_> = (0..=n).map(|_| g.add_node(())).collect();
+        let ground = nodes[n];
+        for (i, under) in self.supported_by.iter().enumerate() {
+            if under.is_empty() {
+                g.add_edge(ground, nodes[i], ());
+            }
+            for &u in under {
+                g.add_edge(nodes[u], nodes[i], ());
+            }
+        }
+        let dom = simple_fast(&g, ground);
+        let mut subtree = vec![1; n];
+        // bricks only rest on bricks that landed before them
+        for i in (0..n).rev() {
+            let idom = dom.immediate_dominator(nodes[i]).unwrap();
+            if idom != ground {
+                subtree[idom.index()] += subtree[i];
+            }
+        }
+        subtree.into_iter().map(|s| s - 1).collect()
+    }
 
-    (bricks, map)
+    /// The bricks that can be disintegrated without anything else moving.
+    pub fn safe(&self) -> Vec<usize> {
+        let chains = self.chain_reactions();
+        (0..self.bricks.len()).filter(|&i| chains[i] == 0).collect()
+    }
 }
 
 #[aoc_generator(day22)]
 fn input_gen(input: &[u8]) -> Result<Vec<Brick>> {
-    Ok(bricks().parse(input)?)
+    parse_bricks(input, false)
 }
 
 #[aoc(day22, part1)]
-fn part1(input: &[Brick]) -> u32 {
-    let (bricks, map) = init(input);
-    let mut ret = 0;
-    for i in 0..bricks.len() {
-        let mut sym_bricks = bricks.clone();
-        let mut sym_map = map.clone();
-        sym_bricks[i].blit(&mut sym_map, false);
-        sym_bricks.remove(i);
-        if fall(&mut sym_bricks, &mut sym_map) == 0 {
-            ret += 1;
-        }
-    }
-    ret
+fn part1(input: &[Brick]) -> usize {
+    Stack::settle(input).safe().len()
 }
 
 #[aoc(day22, part2)]
 fn part2(input: &[Brick]) -> usize {
-    let (bricks, map) = init(input);
-    let mut ret = 0;
-    for i in 0..bricks.len() {
-        let mut sym_bricks = bricks.clone();
-        let mut sym_map = map.clone();
-        sym_bricks[i].blit(&mut sym_map, false);
-        sym_bricks.remove(i);
-        ret += fall(&mut sym_bricks, &mut sym_map);
-    }
-    ret
+    Stack::settle(input).chain_reactions().iter().sum()
 }
 
 #[cfg(test)]
@@ -155,4 +225,61 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(&input_gen(EXAMPLE).unwrap()), 7);
     }
+    #[test]
+    fn supports() {
+        let stack = Stack::settle(&input_gen(EXAMPLE).unwrap());
+        // A holds up B and C, which both hold up D and E
+        assert_eq!(stack.supports[0], [1, 2]);
+        assert_eq!(stack.supported_by[3], [1, 2]);
+        assert_eq!(stack.supported_by[0], [] as [usize; 0]);
+        assert_eq!(stack.bricks[6].from.z, 5);
+        assert_eq!(stack.safe(), [1, 2, 3, 4, 6]);
+        assert_eq!(stack.chain_reactions(), [6, 0, 0, 0, 0, 1, 0]);
+    }
+    #[test]
+    fn validation() {
+        let err = |input: &[u8]| format!("{:#}", parse_bricks(input, false).unwrap_err());
+        assert!(err(b"1,0,1~1,2,1\n1,0,1~1,2").starts_with("line 2: expected x,y,z~x,y,z"));
+        assert_eq!(
+            err(b"1,0,1~1,2,1\n1,2,3~1,0,3"),
+            "line 2: brick goes backwards along y"
+        );
+        assert_eq!(
+            err(b"1,0,0~1,2,0"),
+            "line 1: brick is at z=0, below the lowest level 1"
+        );
+        assert_eq!(
+            err(b"1,0,1~1,2,1\n0,0,2~2,2,2"),
+            "line 2: brick is not a straight line"
+        );
+        assert_eq!(
+            err(b"1,0,1~1,2,1\n0,0,3~0,0,3\n0,1,1~2,1,1"),
+            "bricks on lines 1 and 3 overlap"
+        );
+        assert_eq!(
+            err(b"0,0,5~0,0,9\n1,1,1~1,1,1\n0,0,1~0,0,5"),
+            "bricks on lines 1 and 3 overlap"
+        );
+        let bricks = parse_bricks(b"1,2,1~1,0,1\n0,0,3~0,0,2\n", true).unwrap();
+        assert_eq!(
+            bricks,
+            parse_bricks(b"1,0,1~1,2,1\n0,0,2~0,0,3", false).unwrap()
+        );
+    }
+    #[test]
+    fn tall_stack() {
+        let stack = Stack::settle(
+            &input_gen(
+                b"0,0,3000000~0,0,3000001\n0,0,9000000~5,0,9000000\n5,0,4000000~5,0,7000000",
+            )
+            .unwrap(),
+        );
+        assert_eq!(stack.bricks[0].from.z, 1);
+        assert_eq!(stack.bricks[1].from.z, 1);
+        assert_eq!(stack.bricks[1].to.z, 3000001);
+        assert_eq!(stack.bricks[2].from.z, 3000002);
+        // the short brick doesn't reach the top of the tall one
+        assert_eq!(stack.supported_by[2], [1]);
+        assert_eq!(stack.chain_reactions(), [0, 1, 0]);
+    }
 }
@@ -1,27 +1,37 @@
+use crate::grid::Grid;
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
-use petgraph::graph::{Graph, NodeIndex, UnGraph};
-use pom::parser::*;
-use std::collections::VecDeque;
-use std::collections::{HashMap, HashSet};
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::EdgeType;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
 
-fn tile<'a>() -> Parser<'a, u8, Tile> {
-    sym(b'.').map(|_| Tile::Path)
-        | sym(b'#').map(|_| Tile::Forest)
-        | sym(b'>').map(|_| Tile::SlopeR)
-        | sym(b'<').map(|_| Tile::SlopeL)
-        | sym(b'^').map(|_| Tile::SlopeU)
-        | sym(b'v').map(|_| Tile::SlopeD)
-}
-fn row<'a>() -> Parser<'a, u8, Vec<Tile>> {
-    tile().repeat(1..) - (sym(b'\n').discard() | end())
+fn tile(b: u8) -> Option<Tile> {
+    Some(match b {
+        b'.' => Tile::Path,
+        b'#' => Tile::Forest,
+        b'>' => Tile::SlopeR,
+        b'<' => Tile::SlopeL,
+        b'^' => Tile::SlopeU,
+        b'v' => Tile::SlopeD,
+        _ => return None,
+    })
 }
-fn map<'a>() -> Parser<'a, u8, Vec<Vec<Tile>>> {
-    row().repeat(1..)
+
+fn glyph(t: Tile) -> char {
+    match t {
+        Tile::Path => '.',
+        Tile::Forest => '#',
+        Tile::SlopeR => '>',
+        Tile::SlopeL => '<',
+        Tile::SlopeU => '^',
+        Tile::SlopeD => 'v',
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
-enum Tile {
+pub enum Tile {
     Forest,
     Path,
     SlopeL,
@@ -30,182 +40,323 @@ enum Tile {
     SlopeD,
 }
 
+pub fn parse_map(input: &[u8]) -> Result<Grid<Tile>> {
+    Grid::parse(input, tile)
+}
+
 #[aoc_generator(day23)]
-fn input_gen(input: &[u8]) -> Result<Vec<Vec<Tile>>> {
-    Ok(map().parse(input)?)
+fn input_gen(input: &[u8]) -> Result<Grid<Tile>> {
+    parse_map(input)
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
-struct Node {
-    x: usize,
-    y: usize,
-    tile: Tile,
-}
-
-fn neighbours<'a>(t: &'a Node, map: &'a [Vec<Tile>]) -> impl Iterator<Item = Node> + 'a {
-    let h = map.len() as i64;
-    let w = map[0].len() as i64;
-    [
-        (t.y as i64 - 1, t.x as i64),
-        (t.y as i64 + 1, t.x as i64),
-        (t.y as i64, t.x as i64 - 1),
-        (t.y as i64, t.x as i64 + 1),
-    ]
-    .into_iter()
-    .filter(move |&(y, x)| y > 0 && x > 0 && y < h && x < w)
-    .map(move |(y, x)| {
-        let x = x as usize;
-        let y = y as usize;
-        Node {
-            x,
-            y,
-            tile: map[y][x],
-        }
+/// The map without its slopes, as in part 2.
+pub fn without_slopes(map: &Grid<Tile>) -> Grid<Tile> {
+    map.map(|t| match t {
+        Tile::Forest => Tile::Forest,
+        _ => Tile::Path,
     })
-    .filter(|n| n.tile != Tile::Forest)
-    .filter(move |n| match t.tile {
-        Tile::SlopeL => n.x < t.x,
-        Tile::SlopeR => n.x > t.x,
-        Tile::SlopeU => n.y < t.y,
-        Tile::SlopeD => n.y > t.y,
+}
+
+/// A junction of the trail map.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Node {
+    pub x: usize,
+    pub y: usize,
+    pub tile: Tile,
+}
+
+/// Whether the slope at `from`, if any, lets the hiker step to `to`.
+fn downhill(map: &Grid<Tile>, from: (usize, usize), to: (usize, usize)) -> bool {
+    match map[from] {
+        Tile::SlopeL => to.1 < from.1,
+        Tile::SlopeR => to.1 > from.1,
+        Tile::SlopeU => to.0 < from.0,
+        Tile::SlopeD => to.0 > from.0,
         _ => true,
-    })
+    }
+}
+
+fn exits(map: &Grid<Tile>, p: (usize, usize)) -> Vec<(usize, usize)> {
+    map.neighbours(p)
+        .filter(|&n| map[n] != Tile::Forest)
+        .collect()
 }
 
-fn build_graph(map: &[Vec<Tile>]) -> (Graph<Node, usize>, NodeIndex, NodeIndex) {
-    let mut s_id = None;
-    let mut d_id = None;
-    let mut g = Graph::<Node, usize>::new();
+/// A corridor followed from a junction by [`corridor`].
+struct Corridor {
+    /// Every tile stepped on, ending with the junction or dead end reached.
+    cells: Vec<(usize, usize)>,
+    /// Whether no slope along the way points back.
+    walkable: bool,
+}
+
+/// Follows the corridor leaving `hub` through `first` until it reaches a
+/// tile that `known` accepts, a fork or a dead end.
+fn corridor(
+    map: &Grid<Tile>,
+    hub: (usize, usize),
+    first: (usize, usize),
+    known: impl Fn((usize, usize)) -> bool,
+) -> Corridor {
+    let (mut prev, mut cur) = (hub, first);
+    let mut cells = vec![first];
+    let mut walkable = downhill(map, hub, first);
+    while !known(cur) {
+        let next = exits(map, cur);
+        if next.len() > 2 {
+            break;
+        }
+        let Some(&n) = next.iter().find(|&&n| n != prev) else {
+            break;
+        };
+        walkable &= downhill(map, cur, n);
+        (prev, cur) = (cur, n);
+        cells.push(cur);
+    }
+    Corridor { cells, walkable }
+}
+
+/// Compresses the map into the junctions reachable from the start, joined
+/// by an edge for each corridor that can be walked in that direction,
+/// weighted by its length. Corridors with a slope pointing against the
+/// way they're walked only get an edge the other way. Junctions are the
+/// start, the goal and the tiles where paths fork; dead ends are dropped.
+///
+/// Returns the graph with the start and the goal.
+pub fn build_graph2(map: &Grid<Tile>) -> (Graph<Node, usize>, NodeIndex, NodeIndex) {
+    let path_in_row = |y: usize| (0..map.width()).find(|&x| map[(y, x)] == Tile::Path);
+    let start = (0, path_in_row(0).expect("no start"));
+    let end = (
+        map.height() - 1,
+        path_in_row(map.height() - 1).expect("no goal"),
+    );
+    let node = |(y, x): (usize, usize)| Node {
+        x,
+        y,
+        tile: map[(y, x)],
+    };
+    let mut g = Graph::new();
     let mut ids = HashMap::new();
-    for (y, row) in map.iter().enumerate() {
-        for (x, t) in row.iter().enumerate() {
-            if *t == Tile::Forest {
-                continue;
+    let s_id = g.add_node(node(start));
+    let d_id = g.add_node(node(end));
+    ids.insert(start, s_id);
+    ids.insert(end, d_id);
+    let mut queue = VecDeque::from([start]);
+    while let Some(hub) = queue.pop_front() {
+        for first in exits(map, hub) {
+            let c = corridor(map, hub, first, |p| ids.contains_key(&p));
+            let cur = *c.cells.last().unwrap();
+            if !ids.contains_key(&cur) && exits(map, cur).len() > 2 {
+                ids.insert(cur, g.add_node(node(cur)));
+                queue.push_back(cur);
             }
-            let n = Node { x, y, tile: *t };
-            let id = g.add_node(n);
-            ids.insert((x, y), id);
-            if y == 0 && *t == Tile::Path {
-                s_id = Some(id);
-            } else if y == map.len() - 1 && *t == Tile::Path {
-                d_id = Some(id);
+            if let (Some(&to), true) = (ids.get(&cur), c.walkable) {
+                g.add_edge(ids[&hub], to, c.cells.len());
             }
         }
     }
-    for (y, row) in map.iter().enumerate() {
-        for (x, t) in row.iter().enumerate() {
-            if *t == Tile::Forest {
-                continue;
-            }
-            let node = Node { x, y, tile: *t };
-            let node_id = ids[&(x, y)];
-            for neigh in neighbours(&node, map) {
-                let neigh_id = ids[&(neigh.x, neigh.y)];
-                g.add_edge(node_id, neigh_id, 1);
+    (g, s_id, d_id)
+}
+
+/// The junction graph from [`build_graph2`] as a Graphviz digraph, labelled
+/// with positions and corridor lengths. `start` and `goal` are drawn as
+/// double circles, and the corridors of `hike`, if given, in red.
+pub fn junctions_to_dot(
+    g: &Graph<Node, usize>,
+    start: NodeIndex,
+    goal: NodeIndex,
+    hike: Option<&Hike>,
+) -> String {
+    let on_hike = |a: NodeIndex, b: NodeIndex| {
+        hike.is_some_and(|h| h.junctions.windows(2).any(|w| w == [a, b]))
+    };
+    let mut out = String::from("digraph {\n");
+    for n in g.node_indices() {
+        let Node { x, y, .. } = g[n];
+        let shape = if n == start || n == goal {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        writeln!(
+            out,
+            "    {} [ label = \"{y},{x}\", shape = {shape} ]",
+            n.index()
+        )
+        .unwrap();
+    }
+    for e in g.edge_references() {
+        let (a, b) = (e.source(), e.target());
+        let color = if on_hike(a, b) { ", color = red" } else { "" };
+        writeln!(
+            out,
+            "    {} -> {} [ label = {w}, weight = {w}{color} ]",
+            a.index(),
+            b.index(),
+            w = e.weight()
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The longest hike found by [`longest_path`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hike {
+    pub length: usize,
+    /// The junctions passed, from the start to the goal.
+    pub junctions: Vec<NodeIndex>,
+}
+
+impl Hike {
+    /// Draws the hike over the map the way the puzzle text does: `S` at
+    /// the start and `O` on every tile stepped on. `g` is the junction
+    /// graph from [`build_graph2`] that the hike was found in.
+    ///
+    /// Between two junctions the hike takes the longest corridor, as that's
+    /// the one [`longest_path`] would have picked.
+    pub fn render(&self, map: &Grid<Tile>, g: &Graph<Node, usize>) -> String {
+        let pos = |n: NodeIndex| (g[n].y, g[n].x);
+        let is_junction = |p| g.node_weights().any(|n| (n.y, n.x) == p);
+        let mut glyphs = map.map(|&t| glyph(t));
+        for w in self.junctions.windows(2) {
+            let (from, to) = (pos(w[0]), pos(w[1]));
+            let best = exits(map, from)
+                .into_iter()
+                .map(|first| corridor(map, from, first, is_junction))
+                .filter(|c| c.walkable && c.cells.last() == Some(&to))
+                .max_by_key(|c| c.cells.len())
+                .expect("hike doesn't follow a corridor");
+            for p in best.cells {
+                glyphs[p] = 'O';
             }
         }
+        if let Some(&s) = self.junctions.first() {
+            glyphs[pos(s)] = 'S';
+        }
+        glyphs.render(|&c| c)
     }
-    (g, s_id.unwrap(), d_id.unwrap())
 }
 
-fn build_graph2(map: &[Vec<Tile>]) -> (UnGraph<Node, usize>, NodeIndex, NodeIndex) {
-    let mut g = UnGraph::<Node, usize>::new_undirected();
-    let mut ids = HashMap::new();
-    let mut s = None;
-    let mut d = None;
-    for (y, row) in map.iter().enumerate() {
-        for (x, t) in row.iter().enumerate() {
-            if *t == Tile::Forest {
-                continue;
-            }
-            let n = Node { x, y, tile: *t };
-            if y == 0 && *t == Tile::Path {
-                s = Some(n);
-            } else if y == map.len() - 1 && *t == Tile::Path {
-                d = Some(n);
+struct Search {
+    adj: Vec<Vec<(usize, usize)>>,
+    /// The heaviest edge into each junction.
+    max_in: Vec<usize>,
+    goal: usize,
+    path: Vec<usize>,
+    /// One bit per junction on the current path.
+    visited: Vec<u64>,
+    best: Option<(usize, Vec<usize>)>,
+}
+
+impl Search {
+    fn flip(&mut self, n: usize) {
+        self.visited[n / 64] ^= 1 << (n % 64);
+    }
+    /// `left` bounds what the junctions not yet visited can still add.
+    fn dfs(&mut self, cur: usize, len: usize, left: usize) {
+        if cur == self.goal {
+            if self.best.as_ref().is_none_or(|b| len > b.0) {
+                self.best = Some((len, self.path.clone()));
             }
+            return;
         }
-    }
-    let s = s.unwrap();
-    let d = d.unwrap();
-    let s_id = g.add_node(s);
-    let d_id = g.add_node(d);
-    ids.insert(s, s_id);
-    ids.insert(d, d_id);
-    let mut queue = VecDeque::new();
-    for e in neighbours(&s, map) {
-        queue.push_back((s, e));
-    }
-    let mut visited = HashSet::new();
-    while let Some((from, to)) = queue.pop_front() {
-        if visited.contains(&to) {
-            continue;
+        if self.best.as_ref().is_some_and(|b| len + left <= b.0) {
+            return;
         }
-        visited.insert(to);
-        let mut cur_from = from;
-        let mut cur_to = to;
-        let mut cur_w = 1;
-        let hub = from;
-        loop {
-            let succs: Vec<_> = neighbours(&cur_to, map)
-                .filter(|e| *e != cur_from)
-                .map(|e| (cur_to, e))
-                .collect();
-            let count = succs.len();
-            if count == 1 && succs[0].1 != d {
-                let succ = succs[0];
-                cur_from = succ.0;
-                cur_to = succ.1;
-                cur_w += 1;
+        for i in 0..self.adj[cur].len() {
+            let (next, w) = self.adj[cur][i];
+            if self.visited[next / 64] & 1 << (next % 64) != 0 {
                 continue;
-            } else {
-                let hub_id = *ids.entry(hub).or_insert_with(|| g.add_node(hub));
-                let cur_to_id = *ids.entry(cur_to).or_insert_with(|| g.add_node(cur_to));
-                if g.find_edge(hub_id, cur_to_id).is_none() {
-                    g.add_edge(hub_id, cur_to_id, cur_w);
-                }
-                for succ in succs {
-                    queue.push_back(succ);
-                }
-                break;
             }
+            self.path.push(next);
+            self.flip(next);
+            self.dfs(next, len + w, left - self.max_in[next]);
+            self.flip(next);
+            self.path.pop();
         }
     }
-    (g, s_id, d_id)
 }
 
-#[aoc(day23, part1)]
-fn part1(input: &[Vec<Tile>]) -> usize {
-    let (g, s_id, d_id) = build_graph(input);
+/// The longest path from `from` to `to` that doesn't visit any junction
+/// twice, by depth-first search.
+///
+/// Visited junctions are kept in a bitset. The search gives up on a path
+/// when even entering every junction left through its heaviest edge
+/// couldn't beat the best hike so far. If the goal can only be reached
+/// from one junction, reaching that junction ends the hike, since turning
+/// anywhere else would cut off the goal.
+///
+/// Any number of junctions works, but the search is still exponential in
+/// it: puzzle maps have 36 and take a fraction of a second, while maps
+/// with many more than that may take too long to be useful.
+pub fn longest_path<N, Ty: EdgeType>(
+    g: &Graph<N, usize, Ty>,
+    from: NodeIndex,
+    to: NodeIndex,
+) -> Option<Hike> {
+    let n = g.node_count();
+    let mut adj = vec![vec![]; n];
+    for e in g.edge_references() {
+        let (a, b, w) = (e.source().index(), e.target().index(), *e.weight());
+        adj[a].push((b, w));
+        if !g.is_directed() {
+            adj[b].push((a, w));
+        }
+    }
+    let mut max_in = vec![0; n];
+    for &(b, w) in adj.iter().flatten() {
+        max_in[b] = max_in[b].max(w);
+    }
+    let mut goal = to.index();
+    let mut last_leg = None;
+    let into_goal: Vec<_> = (0..n)
+        .flat_map(|a| {
+            adj[a]
+                .iter()
+                .filter(|e| e.0 == goal)
+                .map(move |&(_, w)| (a, w))
+        })
+        .collect();
+    if let [(last, w)] = into_goal[..] {
+        if last != from.index() {
+            goal = last;
+            last_leg = Some(w);
+        }
+    }
+    let start = from.index();
+    let total = max_in.iter().sum::<usize>() - max_in[start];
+    let mut search = Search {
+        adj,
+        max_in,
+        goal,
+        path: vec![start],
+        visited: vec![0; n.div_ceil(64)],
+        best: None,
+    };
+    search.flip(start);
+    search.dfs(start, 0, total);
+    let (mut length, mut path) = search.best?;
+    if let Some(w) = last_leg {
+        length += w;
+        path.push(to.index());
+    }
+    Some(Hike {
+        length,
+        junctions: path.into_iter().map(NodeIndex::new).collect(),
+    })
+}
 
-    let paths = petgraph::algo::all_simple_paths::<Vec<_>, _>(&g, s_id, d_id, 0, None);
-    paths.map(|p| p.len()).max().unwrap() - 1
+#[aoc(day23, part1)]
+fn part1(input: &Grid<Tile>) -> usize {
+    let (g, s_id, d_id) = build_graph2(input);
+    longest_path(&g, s_id, d_id).expect("no way down").length
 }
 
 #[aoc(day23, part2)]
-fn part2(input: &[Vec<Tile>]) -> usize {
-    let no_slopes: Vec<Vec<Tile>> = input
-        .iter()
-        .map(|row| {
-            row.iter()
-                .map(|t| match t {
-                    Tile::Forest => Tile::Forest,
-                    _ => Tile::Path,
-                })
-                .collect()
-        })
-        .collect();
-    let (g, s_id, d_id) = build_graph2(&no_slopes);
-
-    let paths = petgraph::algo::all_simple_paths::<Vec<_>, _>(&g, s_id, d_id, 0, None);
-    paths
-        .map(|p| {
-            p.windows(2)
-                .map(|w| g.edges_connecting(w[0], w[1]).next().unwrap().weight())
-                .sum::<usize>()
-        })
-        .max()
-        .unwrap()
+fn part2(input: &Grid<Tile>) -> usize {
+    part1(&without_slopes(input))
 }
 
 #[cfg(test)]
@@ -244,4 +395,119 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(&input_gen(EXAMPLE).unwrap()), 154);
     }
+    #[test]
+    fn slopes_are_one_way() {
+        let map = input_gen(EXAMPLE).unwrap();
+        let (g, s_id, _) = build_graph2(&map);
+        let first = g.neighbors(s_id).next().unwrap();
+        assert_eq!((g[first].y, g[first].x), (5, 3));
+        assert_eq!(g[g.find_edge(s_id, first).unwrap()], 15);
+        assert_eq!(g.find_edge(first, s_id), None);
+        let no_slopes = without_slopes(&map);
+        let (h, _, _) = build_graph2(&no_slopes);
+        assert_eq!(h.node_count(), g.node_count());
+        // without slopes corridors go both ways, except that nothing leaves the goal
+        assert_eq!(h.edge_count(), 2 * g.edge_count() - 1);
+    }
+    #[test]
+    fn hike_render() {
+        let map = input_gen(EXAMPLE).unwrap();
+        let (g, s_id, d_id) = build_graph2(&map);
+        let hike = longest_path(&g, s_id, d_id).unwrap();
+        assert_eq!(
+            hike.render(&map, &g),
+            "\
+#S#####################
+#OOOOOOO#########...###
+#######O#########.#.###
+###OOOOO#OOO>.###.#.###
+###O#####O#O#.###.#.###
+###OOOOO#O#O#.....#...#
+###v###O#O#O#########.#
+###...#O#O#OOOOOOO#...#
+#####.#O#O#######O#.###
+#.....#O#O#OOOOOOO#...#
+#.#####O#O#O#########v#
+#.#...#OOO#OOO###OOOOO#
+#.#.#v#######O###O###O#
+#...#.>.#...>OOO#O###O#
+#####v#.#.###v#O#O###O#
+#.....#...#...#O#O#OOO#
+#.#########.###O#O#O###
+#...###...#...#OOO#O###
+###.###.#.###v#####O###
+#...#...#.#.>.>.#.>O###
+#.###.###.#.###.#.#O###
+#.....###...###...#OOO#
+#####################O#
+"
+        );
+        let dot = junctions_to_dot(&g, s_id, d_id, Some(&hike));
+        assert!(dot.contains("    0 [ label = \"0,1\", shape = doublecircle ]\n"));
+        assert!(dot.contains("    0 -> 2 [ label = 15, weight = 15, color = red ]\n"));
+        assert!(dot.contains("    3 -> 5 [ label = 38, weight = 38 ]\n"));
+        let no_slopes = without_slopes(&map);
+        let (h, s_id, d_id) = build_graph2(&no_slopes);
+        let hike = longest_path(&h, s_id, d_id).unwrap();
+        assert_eq!(hike.render(&no_slopes, &h).matches('O').count(), 154);
+    }
+    #[test]
+    fn dot_ends() {
+        // built by hand with the start and the goal added last
+        let node = |y, x| Node {
+            x,
+            y,
+            tile: Tile::Path,
+        };
+        let mut g = Graph::new();
+        let mid = g.add_node(node(2, 2));
+        let goal = g.add_node(node(4, 3));
+        let start = g.add_node(node(0, 1));
+        g.add_edge(start, mid, 3);
+        g.add_edge(mid, goal, 3);
+        assert_eq!(
+            junctions_to_dot(&g, start, goal, None),
+            r#"digraph {
+    0 [ label = "2,2", shape = circle ]
+    1 [ label = "4,3", shape = doublecircle ]
+    2 [ label = "0,1", shape = doublecircle ]
+    2 -> 0 [ label = 3, weight = 3 ]
+    0 -> 1 [ label = 3, weight = 3 ]
+}
+"#
+        );
+    }
+    #[test]
+    fn junction_path() {
+        // a square with a heavy side, a shortcut and a single way to the goal
+        let mut g = petgraph::graph::UnGraph::<(), usize>::new_undirected();
+        let n: Vec<_> = (0..5).map(|_| g.add_node(())).collect();
+        g.add_edge(n[0], n[1], 1);
+        g.add_edge(n[1], n[3], 1);
+        g.add_edge(n[0], n[2], 5);
+        g.add_edge(n[2], n[3], 5);
+        g.add_edge(n[1], n[2], 1);
+        g.add_edge(n[3], n[4], 2);
+        let hike = longest_path(&g, n[0], n[4]).unwrap();
+        assert_eq!(hike.length, 5 + 5 + 2);
+        assert_eq!(hike.junctions, [n[0], n[2], n[3], n[4]]);
+        let mut d = Graph::<(), usize>::new();
+        let m: Vec<_> = (0..3).map(|_| d.add_node(())).collect();
+        d.add_edge(m[1], m[0], 1);
+        d.add_edge(m[1], m[2], 1);
+        assert_eq!(longest_path(&d, m[0], m[2]), None);
+    }
+    #[test]
+    fn many_junctions() {
+        // a chain of 100 junctions, each pair joined by a short and a long corridor
+        let mut g = Graph::<(), usize>::new();
+        let n: Vec<_> = (0..100).map(|_| g.add_node(())).collect();
+        for w in n.windows(2) {
+            g.add_edge(w[0], w[1], 1);
+            g.add_edge(w[0], w[1], 2);
+        }
+        let hike = longest_path(&g, n[0], n[99]).unwrap();
+        assert_eq!(hike.length, 2 * 99);
+        assert_eq!(hike.junctions, n);
+    }
 }
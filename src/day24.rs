@@ -1,8 +1,11 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use nalgebra::{Matrix2, Matrix6, Vector2, Vector3, Vector6};
+use nalgebra::{Matrix3, Vector2, Vector3};
+use num::rational::Ratio;
+use num::{BigRational, ToPrimitive, Zero};
 use pom::parser::*;
+use std::collections::HashMap;
 
 fn integer<'a>() -> Parser<'a, u8, i64> {
     let unsigned = (one_of(b"123456789") - one_of(b"0123456789").repeat(0..)) | sym(b'0');
@@ -17,9 +20,9 @@ fn spaced_sym<'a>(s: u8) -> Parser<'a, u8, ()> {
     (sym(b' ').repeat(0..) - sym(s) - sym(b' ').repeat(0..)).discard()
 }
 
-fn coords<'a>() -> Parser<'a, u8, Vector3<f64>> {
+fn coords<'a>() -> Parser<'a, u8, Vector3<i64>> {
     (integer() - spaced_sym(b',') + integer() - spaced_sym(b',') + integer())
-        .map(|((x, y), z)| Vector3::new(x as f64, y as f64, z as f64))
+        .map(|((x, y), z)| Vector3::new(x, y, z))
 }
 
 fn hail<'a>() -> Parser<'a, u8, Hail> {
@@ -31,37 +34,195 @@ fn hails<'a>() -> Parser<'a, u8, Vec<Hail>> {
     hail().repeat(1..) - end()
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-struct Hail {
-    pos: Vector3<f64>,
-    vel: Vector3<f64>,
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Hail {
+    pub pos: Vector3<i64>,
+    pub vel: Vector3<i64>,
 }
 
-fn collision_2d(h1: &Hail, h2: &Hail) -> Option<Vector2<f64>> {
-    let p1 = h1.pos.xy();
-    let p2 = h2.pos.xy();
-    let v1 = h1.vel.xy();
-    let v2 = h2.vel.xy();
-    if v1 == v2 {
-        if p1 == p2 {
-            return Some(p1);
-        } else {
+/// Exact times and coordinates.
+pub type Frac = Ratio<i128>;
+
+/// When two stones are closest to each other, and how far apart they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approach {
+    pub t: Frac,
+    /// The exact square of `distance`.
+    pub squared: BigRational,
+    pub distance: f64,
+}
+
+fn big(x: Frac) -> BigRational {
+    BigRational::new((*x.numer()).into(), (*x.denom()).into())
+}
+
+impl Hail {
+    /// Where the stone is at time `t`.
+    pub fn at(&self, t: Frac) -> Vector3<Frac> {
+        widen(self.pos).zip_map(&widen(self.vel), |p, v| t * v + p)
+    }
+
+    /// When from now on the two stones are closest.
+    pub fn closest_approach(&self, other: &Hail) -> Approach {
+        self.closest_approach_between(other, Frac::zero(), None)
+            .expect("the window has no end")
+    }
+
+    /// When between `from` and `to` the two stones are closest, with no end
+    /// if `to` is `None`, or `None` if `to` comes before `from`.
+    pub fn closest_approach_between(
+        &self,
+        other: &Hail,
+        from: Frac,
+        to: Option<Frac>,
+    ) -> Option<Approach> {
+        if to.is_some_and(|to| to < from) {
             return None;
         }
+        let (dp, dv) = (
+            widen(other.pos) - widen(self.pos),
+            widen(other.vel) - widen(self.vel),
+        );
+        // the squared distance |dp + dv·t|² is a parabola in t
+        let speed2 = dv.dot(&dv);
+        let mut t = if speed2 == 0 {
+            from
+        } else {
+            Frac::new(-dp.dot(&dv), speed2).max(from)
+        };
+        if let Some(to) = to {
+            t = t.min(to);
+        }
+        // the squares can overflow i128 at puzzle magnitudes
+        let squared: BigRational = dp
+            .zip_map(&dv, |p, v| t * v + p)
+            .iter()
+            .map(|&x| big(x) * big(x))
+            .sum();
+        let distance = squared.to_f64().unwrap_or(f64::INFINITY).sqrt();
+        Some(Approach {
+            t,
+            squared,
+            distance,
+        })
+    }
+}
+
+/// The pairs of stones that come within `d` of each other at some time
+/// between `from` and `to`, with when they are closest. The distances are
+/// compared exactly.
+pub fn close_pairs(
+    hails: &[Hail],
+    d: Frac,
+    from: Frac,
+    to: Frac,
+) -> Result<Vec<(usize, usize, Approach)>> {
+    if to < from {
+        bail!("the window ends at {to}, before it starts at {from}");
+    }
+    let d2 = big(d) * big(d);
+    Ok((0..hails.len())
+        .tuple_combinations()
+        .filter_map(|(i, j)| {
+            let a = hails[i].closest_approach_between(&hails[j], from, Some(to))?;
+            (a.squared <= d2).then_some((i, j, a))
+        })
+        .collect())
+}
+
+/// How the paths of two hailstones relate when ignoring the Z axis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Collision2d {
+    /// The paths cross ahead of both stones: the first one gets there at
+    /// `t1`, the second one at `t2`.
+    Crossing {
+        t1: Frac,
+        t2: Frac,
+        x: Frac,
+        y: Frac,
+    },
+    /// The lines cross, but behind at least one of the stones.
+    Past { t1: Frac, t2: Frac },
+    /// The paths never meet.
+    Parallel,
+    /// The paths run along the same line and share a stretch of it ahead
+    /// of both stones.
+    CollinearOverlap,
+    /// The paths run along the same line, but the stones head apart.
+    CollinearApart,
+}
+
+fn cross_2d(a: Vector2<i128>, b: Vector2<i128>) -> i128 {
+    a.x * b.y - a.y * b.x
+}
+
+/// The stretch of a line covered by a path starting at `s` and moving in
+/// direction `dir`, in units along the line.
+fn ray(s: i128, dir: i128) -> (Option<i128>, Option<i128>) {
+    match dir.signum() {
+        1 => (Some(s), None),
+        -1 => (None, Some(s)),
+        _ => (Some(s), Some(s)),
+    }
+}
+
+pub fn collision_2d(h1: &Hail, h2: &Hail) -> Collision2d {
+    let (p1, p2) = (widen(h1.pos).xy(), widen(h2.pos).xy());
+    let (v1, v2) = (widen(h1.vel).xy(), widen(h2.vel).xy());
+    let d = p2 - p1;
+    let det = cross_2d(v1, v2);
+    if det != 0 {
+        let t1 = Frac::new(cross_2d(d, v2), det);
+        let t2 = Frac::new(cross_2d(d, v1), det);
+        if t1 < Frac::zero() || t2 < Frac::zero() {
+            return Collision2d::Past { t1, t2 };
+        }
+        let x = Frac::from(p1.x) + t1 * v1.x;
+        let y = Frac::from(p1.y) + t1 * v1.y;
+        return Collision2d::Crossing { t1, t2, x, y };
     }
-    let m = Matrix2::from_columns(&[v1, -v2]);
-    let b = p2 - p1;
-    let Some(m_inv) = m.try_inverse() else {
-        return None;
+    // parallel directions, or one of the stones doesn't move
+    let line = if v1 != Vector2::zeros() { v1 } else { v2 };
+    if line == Vector2::zeros() {
+        return if d == Vector2::zeros() {
+            Collision2d::CollinearOverlap
+        } else {
+            Collision2d::Parallel
+        };
+    }
+    if cross_2d(d, line) != 0 {
+        return Collision2d::Parallel;
+    }
+    let (lo1, hi1) = ray(p1.dot(&line), v1.dot(&line));
+    let (lo2, hi2) = ray(p2.dot(&line), v2.dot(&line));
+    let lo = lo1.max(lo2);
+    let hi = match (hi1, hi2) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
     };
-    let a = m_inv * b;
-    if a > Vector2::zeros() {
-        Some(v1 * a.x + p1)
-    } else {
-        None
+    match (lo, hi) {
+        (Some(lo), Some(hi)) if lo > hi => Collision2d::CollinearApart,
+        _ => Collision2d::CollinearOverlap,
     }
 }
 
+/// Every pair of hailstones with how their paths relate.
+pub fn events_2d(hails: &[Hail]) -> Vec<(usize, usize, Collision2d)> {
+    (0..hails.len())
+        .tuple_combinations()
+        .map(|(i, j)| (i, j, collision_2d(&hails[i], &hails[j])))
+        .collect()
+}
+
+fn widen(v: Vector3<i64>) -> Vector3<i128> {
+    v.map(i128::from)
+}
+
+/// The matrix `M` with `M * b == a.cross(&b)`.
+fn cross_matrix(a: Vector3<i128>) -> Matrix3<i128> {
+    Matrix3::new(0, -a.z, a.y, a.z, 0, -a.x, -a.y, a.x, 0)
+}
+
 //(p0 - p[i]) x (v0 - v[i]) == 0
 // p0 x v0 - p0 x v[i] - p[i] x v0 + p[i] x v[i] = 0
 // p0 x v0 = p0 x v[i] + p[i] x v0 - p[i] x v[i]
@@ -77,75 +238,307 @@ fn collision_2d(h1: &Hail, h2: &Hail) -> Option<Vector2<f64>> {
 //  [a*p0 + b*v0]
 //  [c*p0 + d*v0]
 
-fn solve(h1: Hail, h2: Hail, h3: Hail) -> Vector3<f64> {
-    let v1 = (h1.vel - h2.vel).cross_matrix();
-    let v2 = (h1.vel - h3.vel).cross_matrix();
-    let p1 = (h2.pos - h1.pos).cross_matrix();
-    let p2 = (h3.pos - h1.pos).cross_matrix();
-    let c1 = -h1.pos.cross(&h1.vel) + h2.pos.cross(&h2.vel);
-    let c2 = -h1.pos.cross(&h1.vel) + h3.pos.cross(&h3.vel);
-    let mut c: Vector6<f64> = Vector6::zeros();
-    let mut c1v = c.fixed_view_mut::<3, 1>(0, 0);
-    c1v += c1;
-    let mut c2v = c.fixed_view_mut::<3, 1>(3, 0);
-    c2v += c2;
-    let mut m = Matrix6::zeros();
-    let mut m11v = m.fixed_view_mut::<3, 3>(0, 0);
-    m11v += v1;
-    let mut m21v = m.fixed_view_mut::<3, 3>(3, 0);
-    m21v += v2;
-    let mut m12v = m.fixed_view_mut::<3, 3>(0, 3);
-    m12v += p1;
-    let mut m22v = m.fixed_view_mut::<3, 3>(3, 3);
-    m22v += p2;
-    let m_inv = m.try_inverse().unwrap();
-    let x = m_inv * c;
-    x.fixed_view::<3, 1>(0, 0).into()
-}
-
-fn collisions_2d_within(hails: &[Hail], min: Vector2<f64>, max: Vector2<f64>) -> usize {
+/// The rock's position and velocity, as far as three hailstones tell, or
+/// `None` if they don't pin them down.
+fn solve(h1: &Hail, h2: &Hail, h3: &Hail) -> Option<Vec<BigRational>> {
+    let (p1, p2, p3) = (widen(h1.pos), widen(h2.pos), widen(h3.pos));
+    let (v1, v2, v3) = (widen(h1.vel), widen(h2.vel), widen(h3.vel));
+    let blocks = [
+        (cross_matrix(v1 - v2), cross_matrix(p2 - p1)),
+        (cross_matrix(v1 - v3), cross_matrix(p3 - p1)),
+    ];
+    let c = [
+        -p1.cross(&v1) + p2.cross(&v2),
+        -p1.cross(&v1) + p3.cross(&v3),
+    ];
+    let mut m: Vec<Vec<BigRational>> = vec![];
+    for ((v, p), c) in blocks.iter().zip(c) {
+        for r in 0..3 {
+            let row = (0..3).map(|i| v[(r, i)]).chain((0..3).map(|i| p[(r, i)]));
+            let row = row.chain([c[r]]);
+            m.push(row.map(|x| BigRational::from_integer(x.into())).collect());
+        }
+    }
+    gauss(m)
+}
+
+/// Solves the square system whose augmented matrix is `m` exactly.
+fn gauss(mut m: Vec<Vec<BigRational>>) -> Option<Vec<BigRational>> {
+    let n = m.len();
+    for col in 0..n {
+        let pivot = (col..n).find(|&r| !m[r][col].is_zero())?;
+        m.swap(col, pivot);
+        let p = m[col].clone();
+        for row in m.iter_mut().skip(col + 1) {
+            if row[col].is_zero() {
+                continue;
+            }
+            let f = &row[col] / &p[col];
+            for (x, y) in row.iter_mut().zip(&p).skip(col) {
+                *x -= &f * y;
+            }
+        }
+    }
+    let mut x = vec![BigRational::zero(); n];
+    for r in (0..n).rev() {
+        let mut rhs = m[r][n].clone();
+        for c in r + 1..n {
+            rhs -= &m[r][c] * &x[c];
+        }
+        x[r] = rhs / &m[r][r];
+    }
+    Some(x)
+}
+
+/// When `rock` hits `h`, if ever.
+fn hit_time(rock: &Hail, h: &Hail) -> Option<Frac> {
+    let (dp, dv) = (
+        widen(h.pos) - widen(rock.pos),
+        widen(rock.vel) - widen(h.vel),
+    );
+    let t = match (0..3).find(|&i| dv[i] != 0) {
+        Some(i) => Frac::new(dp[i], dv[i]),
+        None => Frac::zero(),
+    };
+    (t >= Frac::zero() && dp.map(Frac::from) == dv.map(|v| t * v)).then_some(t)
+}
+
+/// The stones in the order the rock hits them, with the time of each hit.
+pub fn hit_order(rock: &Hail, hails: &[Hail]) -> Result<Vec<(usize, Frac)>> {
+    let mut hits = vec![];
+    for (i, h) in hails.iter().enumerate() {
+        match hit_time(rock, h) {
+            Some(t) => hits.push((i, t)),
+            None => bail!("the rock misses hailstone {}", i + 1),
+        }
+    }
+    hits.sort_by_key(|&(i, t)| (t, i));
+    Ok(hits)
+}
+
+/// Where and how to throw a rock so that it hits every hailstone.
+///
+/// The first three hailstones whose equations are independent determine
+/// the only possible throw, which is then checked against the others.
+pub fn throw(hails: &[Hail]) -> Result<Hail> {
+    let Some(x) = hails
+        .iter()
+        .tuple_combinations()
+        .find_map(|(a, b, c)| solve(a, b, c))
+    else {
+        bail!("no three hailstones determine the throw");
+    };
+    let mut v = [0; 6];
+    for (v, x) in v.iter_mut().zip(&x) {
+        if !x.is_integer() {
+            bail!("the rock would have to start at a fractional position or speed");
+        }
+        *v = x
+            .to_integer()
+            .to_i64()
+            .context("the throw doesn't fit in 64 bits")?;
+    }
+    let rock = Hail {
+        pos: Vector3::new(v[0], v[1], v[2]),
+        vel: Vector3::new(v[3], v[4], v[5]),
+    };
+    hit_order(&rock, hails).context("the only candidate throw doesn't work")?;
+    Ok(rock)
+}
+
+/// Whether [`collision_2d`] would report a [`Collision2d::Crossing`]
+/// with both X and Y between `min` and `max`, without dividing: the times
+/// and the crossing point are compared multiplied by `det`, with its sign
+/// flipped to keep it positive.
+fn crosses_within(h1: &Hail, h2: &Hail, min: i128, max: i128) -> bool {
+    let (p1, p2) = (widen(h1.pos).xy(), widen(h2.pos).xy());
+    let (v1, v2) = (widen(h1.vel).xy(), widen(h2.vel).xy());
+    let d = p2 - p1;
+    let det = cross_2d(v1, v2);
+    let sign = det.signum();
+    let (det, t1, t2) = (det * sign, cross_2d(d, v2) * sign, cross_2d(d, v1) * sign);
+    let within = |p: i128, v: i128| (min * det..=max * det).contains(&(p * det + t1 * v));
+    det != 0 && t1 >= 0 && t2 >= 0 && within(p1.x, v1.x) && within(p1.y, v1.y)
+}
+
+/// The pairs of hailstones whose paths cross ahead of both of them, at a
+/// point with both X and Y between `min` and `max`, checked one by one.
+pub fn collisions_2d_within_brute(hails: &[Hail], min: i64, max: i64) -> usize {
+    let (min, max) = (min as i128, max as i128);
+    hails
+        .iter()
+        .tuple_combinations()
+        .filter(|(h1, h2)| crosses_within(h1, h2, min, max))
+        .count()
+}
+
+/// How a path meets the test area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Clip {
+    Miss,
+    /// It crosses the area from one point of the border to another, given
+    /// by their distance along the border counter-clockwise from the
+    /// `(min, min)` corner.
+    Chord(Frac, Frac),
+    /// It starts inside, grazes a corner or runs along a side.
+    Other,
+}
+
+/// Clips a path to the test area, Liang–Barsky style.
+fn clip(h: &Hail, min: i64, max: i64) -> Clip {
+    let (min, max) = (min as i128, max as i128);
+    let (p, v) = (widen(h.pos).xy(), widen(h.vel).xy());
+    let inside = |i: usize| min < p[i] && p[i] < max;
+    if inside(0) && inside(1) {
+        return Clip::Other;
+    }
+    let (mut enter, mut leave) = (Frac::zero(), None::<Frac>);
+    for i in 0..2 {
+        if v[i] == 0 {
+            if p[i] < min || p[i] > max {
+                return Clip::Miss;
+            }
+            if p[i] == min || p[i] == max {
+                return Clip::Other;
+            }
+            continue;
+        }
+        let (a, b) = (Frac::new(min - p[i], v[i]), Frac::new(max - p[i], v[i]));
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        enter = enter.max(a);
+        leave = Some(leave.map_or(b, |l| l.min(b)));
+    }
+    // both velocities are 0 only for stones standing still
+    let Some(leave) = leave else {
+        return Clip::Other;
+    };
+    if enter > leave {
+        return Clip::Miss;
+    }
+    if enter == leave {
+        return Clip::Other;
+    }
+    let (min, max) = (Frac::from(min), Frac::from(max));
+    let side = max - min;
+    let border = |t: Frac| {
+        let (x, y) = (t * v.x + p.x, t * v.y + p.y);
+        if y == min {
+            x - min
+        } else if x == max {
+            side + (y - min)
+        } else if y == max {
+            side * 2 + (max - x)
+        } else {
+            side * 3 + (max - y)
+        }
+    };
+    Clip::Chord(border(enter), border(leave))
+}
+
+/// Counts the same pairs as [`collisions_2d_within_brute`].
+///
+/// Each path is clipped to a chord of the area. Two chords whose ends are
+/// all distinct cross exactly when their ends interleave along the border,
+/// and those pairs are counted with a Fenwick tree in O(n log n). Paths
+/// starting inside the area, grazing it, or sharing a border point with
+/// another path are checked against every other path with the same
+/// integer test as the brute force.
+///
+/// A path starting inside only reaches the border at one end, and whether
+/// it crosses a chord then depends on which side of the chord its start
+/// lies, which the order along the border doesn't tell. Puzzle inputs are
+/// mostly such stones, and most of their pairs do cross, so there the
+/// count is quadratic anyway and the sweep only saves the pairs of stones
+/// starting outside.
+pub fn collisions_2d_within(hails: &[Hail], min: i64, max: i64) -> usize {
+    let clips: Vec<_> = hails.iter().map(|h| clip(h, min, max)).collect();
+    let mut ends: HashMap<&Frac, usize> = HashMap::new();
+    for c in &clips {
+        if let Clip::Chord(a, b) = c {
+            *ends.entry(a).or_default() += 1;
+            *ends.entry(b).or_default() += 1;
+        }
+    }
+    let mut chords = vec![];
+    let mut others = vec![];
+    for (i, c) in clips.iter().enumerate() {
+        match c {
+            Clip::Chord(a, b) if ends[a] == 1 && ends[b] == 1 => {
+                chords.push(if a < b { (a, b) } else { (b, a) })
+            }
+            Clip::Miss => {}
+            _ => others.push(i),
+        }
+    }
+    // a chord started earlier crosses this one if it ends between its ends
+    chords.sort();
+    let mut stops: Vec<_> = chords.iter().map(|c| c.1).collect();
+    stops.sort();
+    let mut started = Fenwick::new(stops.len());
     let mut ret = 0;
-    for pair in hails.iter().combinations(2) {
-        let [h1, h2] = pair.as_slice() else {
-            unreachable!()
-        };
-        if let Some(c) = collision_2d(h1, h2) {
-            if c >= min && c <= max {
-                ret += 1;
+    for &(a, b) in &chords {
+        let from = stops.partition_point(|&s| s <= a);
+        let to = stops.partition_point(|&s| s < b);
+        ret += started.sum(to) - started.sum(from);
+        started.add(to);
+    }
+    let mut other = vec![false; hails.len()];
+    for &i in &others {
+        other[i] = true;
+    }
+    let (min, max) = (min as i128, max as i128);
+    for &i in &others {
+        for (j, h) in hails.iter().enumerate() {
+            // pairs of two others are only counted from the first one
+            if (j > i || !other[j]) && clips[j] != Clip::Miss {
+                ret += crosses_within(&hails[i], h, min, max) as usize;
             }
         }
     }
     ret
 }
 
+/// Prefix sums over counters.
+struct Fenwick(Vec<usize>);
+impl Fenwick {
+    fn new(n: usize) -> Self {
+        Fenwick(vec![0; n + 1])
+    }
+    fn add(&mut self, i: usize) {
+        let mut i = i + 1;
+        while i < self.0.len() {
+            self.0[i] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+    /// The sum of counters `0..i`.
+    fn sum(&self, mut i: usize) -> usize {
+        let mut ret = 0;
+        while i > 0 {
+            ret += self.0[i];
+            i -= i & i.wrapping_neg();
+        }
+        ret
+    }
+}
+
+pub fn parse_hails(input: &[u8]) -> Result<Vec<Hail>> {
+    Ok(hails().parse(input)?)
+}
+
 #[aoc_generator(day24)]
 fn input_gen(input: &[u8]) -> Result<Vec<Hail>> {
-    Ok(hails().parse(input)?)
+    parse_hails(input)
 }
 
 #[aoc(day24, part1)]
 fn part1(input: &[Hail]) -> usize {
-    collisions_2d_within(
-        input,
-        Vector2::from_element(200000000000000.),
-        Vector2::from_element(400000000000000.),
-    )
+    collisions_2d_within(input, 200000000000000, 400000000000000)
 }
 
 #[aoc(day24, part2)]
-fn part2(input: &[Hail]) -> usize {
-    let avg: Vector3<f64> =
-        input.iter().map(|h| h.pos).sum::<Vector3<f64>>() / (input.len() as f64);
-    let input: Vec<_> = input
-        .iter()
-        .copied()
-        .map(|mut h| {
-            h.pos -= avg;
-            h
-        })
-        .collect();
-    let x = solve(input[0], input[1], input[2]) + avg;
-    x.into_iter().map(|i| i.round() as usize).sum()
+fn part2(input: &[Hail]) -> Result<i64> {
+    Ok(throw(input)?.pos.sum())
 }
 
 #[cfg(test)]
@@ -160,17 +553,197 @@ mod tests {
 
     #[test]
     fn part1_example() {
+        let hails = input_gen(EXAMPLE).unwrap();
+        assert_eq!(collisions_2d_within(&hails, 7, 27), 2);
+        assert_eq!(collisions_2d_within_brute(&hails, 7, 27), 2);
+    }
+    #[test]
+    fn example_events() {
+        let hails = input_gen(EXAMPLE).unwrap();
+        let events = events_2d(&hails);
+        assert_eq!(events.len(), 10);
+        let f = |n, d| Frac::new(n, d);
         assert_eq!(
-            collisions_2d_within(
-                &input_gen(EXAMPLE).unwrap(),
-                Vector2::from_element(7.),
-                Vector2::from_element(27.)
-            ),
-            2
+            events[0].2,
+            Collision2d::Crossing {
+                t1: f(7, 3),
+                t2: f(11, 3),
+                x: f(43, 3),
+                y: f(46, 3)
+            }
+        );
+        assert_eq!(events[4].2, Collision2d::Parallel);
+        assert!(matches!(events[3].2, Collision2d::Past { .. }));
+    }
+    #[test]
+    fn sweep_matches_brute_force() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut rand = |n: i64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % (2 * n as u64 + 1)) as i64 - n
+        };
+        for round in 0..500 {
+            // small coordinates make shared border points, corners, sides
+            // and parallel paths common
+            let (r, speed) = if round % 2 == 0 { (6, 3) } else { (1000, 50) };
+            let hails: Vec<_> = (0..2 + round % 40)
+                .map(|_| Hail {
+                    pos: Vector3::new(rand(r), rand(r), 0),
+                    vel: Vector3::new(rand(speed), rand(speed), 0),
+                })
+                .collect();
+            let (min, max) = (-r / 3, r / 2);
+            let range = Frac::from(min as i128)..=Frac::from(max as i128);
+            let exact = events_2d(&hails)
+                .iter()
+                .filter(|e| match &e.2 {
+                    Collision2d::Crossing { x, y, .. } => range.contains(x) && range.contains(y),
+                    _ => false,
+                })
+                .count();
+            assert_eq!(
+                collisions_2d_within_brute(&hails, min, max),
+                exact,
+                "{hails:?}"
+            );
+            assert_eq!(collisions_2d_within(&hails, min, max), exact, "{hails:?}");
+        }
+    }
+    #[test]
+    fn collinear() {
+        let h = |p: (i64, i64), v: (i64, i64)| Hail {
+            pos: Vector3::new(p.0, p.1, 0),
+            vel: Vector3::new(v.0, v.1, 0),
+        };
+        // same line, same direction, different speeds
+        let c = collision_2d(&h((0, 0), (1, 1)), &h((5, 5), (2, 2)));
+        assert_eq!(c, Collision2d::CollinearOverlap);
+        // towards each other
+        let c = collision_2d(&h((0, 0), (1, 1)), &h((5, 5), (-3, -3)));
+        assert_eq!(c, Collision2d::CollinearOverlap);
+        // away from each other
+        let c = collision_2d(&h((0, 0), (-1, -1)), &h((5, 5), (3, 3)));
+        assert_eq!(c, Collision2d::CollinearApart);
+        let c = collision_2d(&h((0, 0), (1, 1)), &h((0, 1), (2, 2)));
+        assert_eq!(c, Collision2d::Parallel);
+        // a stone standing still on the other's path, then behind it
+        let c = collision_2d(&h((0, 0), (1, 2)), &h((2, 4), (0, 0)));
+        assert_eq!(c, Collision2d::CollinearOverlap);
+        let c = collision_2d(&h((0, 0), (1, 2)), &h((-2, -4), (0, 0)));
+        assert_eq!(c, Collision2d::CollinearApart);
+        // crossing right where one of them is now
+        let c = collision_2d(&h((0, 0), (1, 0)), &h((3, 0), (0, 1)));
+        assert!(matches!(c, Collision2d::Crossing { t2, .. } if t2 == Frac::zero()));
+    }
+    #[test]
+    fn kinematics() {
+        let hails = input_gen(EXAMPLE).unwrap();
+        let rock = throw(&hails).unwrap();
+        assert_eq!(
+            hit_order(&rock, &hails).unwrap(),
+            [(4, 1), (1, 3), (2, 4), (0, 5), (3, 6)].map(|(i, t)| (i, Frac::from(t)))
+        );
+        let five = Frac::from(5);
+        assert_eq!(hails[0].at(five), rock.at(five));
+        assert_eq!(hails[0].at(five), Vector3::new(9, 18, 20).map(Frac::from));
+        assert_eq!(rock.closest_approach(&hails[3]).distance, 0.);
+
+        let a = hails[0].closest_approach(&hails[1]);
+        assert_eq!(a.t, Frac::new(13, 5));
+        assert!((a.distance - 67.2f64.sqrt()).abs() < 1e-9);
+        let a = hails[0]
+            .closest_approach_between(&hails[1], Frac::zero(), Some(Frac::from(2)))
+            .unwrap();
+        assert_eq!(a.t, Frac::from(2));
+        assert_eq!(a.squared, BigRational::from_integer(69.into()));
+        assert!((a.distance - 69f64.sqrt()).abs() < 1e-9);
+        assert_eq!(
+            hails[0].closest_approach_between(&hails[1], Frac::from(2), Some(Frac::from(1))),
+            None
+        );
+
+        let close: Vec<_> = close_pairs(&hails, Frac::new(41, 5), Frac::zero(), Frac::from(10))
+            .unwrap()
+            .into_iter()
+            .map(|(i, j, _)| (i, j))
+            .collect();
+        assert!(close.contains(&(0, 1)));
+        assert!(
+            close_pairs(&hails, Frac::new(819, 100), Frac::zero(), Frac::from(10))
+                .unwrap()
+                .iter()
+                .all(|&(i, j, _)| (i, j) != (0, 1))
+        );
+        assert!(close_pairs(&hails, Frac::from(1), Frac::from(2), Frac::from(1)).is_err());
+
+        // 2⁵³ + 1 rounds down to 2⁵³ as an f64
+        let far = 1i64 << 53;
+        let still = |x| Hail {
+            pos: Vector3::new(x, 0, 0),
+            vel: Vector3::zeros(),
+        };
+        let pair = [still(0), still(far + 1)];
+        let window = (Frac::zero(), Frac::from(1));
+        assert!(
+            close_pairs(&pair, Frac::from(far as i128), window.0, window.1)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            close_pairs(&pair, Frac::from(far as i128 + 1), window.0, window.1)
+                .unwrap()
+                .len(),
+            1
         );
     }
     #[test]
     fn part2_example() {
-        assert_eq!(part2(&input_gen(EXAMPLE).unwrap()), 47);
+        assert_eq!(part2(&input_gen(EXAMPLE).unwrap()).unwrap(), 47);
+    }
+    #[test]
+    fn throw_checks() {
+        let mut hails = input_gen(EXAMPLE).unwrap();
+        let rock = Hail {
+            pos: Vector3::new(24, 13, 10),
+            vel: Vector3::new(-3, 1, 2),
+        };
+        assert_eq!(throw(&hails).unwrap(), rock);
+        assert_eq!(hit_time(&rock, &hails[0]), Some(Frac::from(5)));
+        // a repeated stone makes the first triple singular
+        hails.insert(1, hails[0]);
+        assert_eq!(throw(&hails).unwrap(), rock);
+        hails.push(Hail {
+            pos: Vector3::new(1, 2, 3),
+            vel: Vector3::new(1, 1, 1),
+        });
+        assert_eq!(
+            format!("{:#}", throw(&hails).unwrap_err()),
+            "the only candidate throw doesn't work: the rock misses hailstone 7"
+        );
+        let parallel =
+            input_gen(b"0, 0, 0 @ 1, 0, 0\n0, 1, 0 @ 1, 0, 0\n0, 2, 0 @ 1, 0, 0").unwrap();
+        assert!(throw(&parallel).is_err());
+    }
+    #[test]
+    fn half_nanosecond_hit() {
+        let rock = Hail {
+            pos: Vector3::new(0, 0, 0),
+            vel: Vector3::new(2, 0, 0),
+        };
+        let still = Hail {
+            pos: Vector3::new(1, 0, 0),
+            vel: Vector3::zeros(),
+        };
+        let half = Frac::new(1, 2);
+        assert_eq!(rock.at(half), still.at(half));
+        assert_eq!(rock.closest_approach(&still).distance, 0.);
+        assert_eq!(hit_order(&rock, &[still]).unwrap(), [(0, half)]);
+        let behind = Hail {
+            pos: Vector3::new(-1, 0, 0),
+            vel: Vector3::zeros(),
+        };
+        assert!(hit_order(&rock, &[still, behind]).is_err());
     }
 }
@@ -1,11 +1,13 @@
-use anyhow::Result;
+use crate::mincut::{edge_connectivity, k_edge_connected_subgraphs, stoer_wagner};
+use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use petgraph::graph::{NodeIndex, UnGraph};
-use petgraph::visit::EdgeRef;
 use pom::parser::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
 
-type Graph = UnGraph<usize, usize>;
+/// Components as nodes, wires as edges.
+pub type Wiring = UnGraph<String, ()>;
 
 fn ident<'a>() -> Parser<'a, u8, String> {
     one_of(b"abcdefghijklmnopqrstuvwxyz")
@@ -23,129 +25,139 @@ fn items<'a>() -> Parser<'a, u8, Vec<(String, Vec<String>)>> {
     item().repeat(1..) - end()
 }
 
-#[aoc_generator(day25)]
-fn input_gen(input: &[u8]) -> Result<Graph> {
+/// Reads the `name: other names` lines into one node per component.
+pub fn parse_wiring(input: &[u8]) -> Result<Wiring> {
     let list = items().parse(input)?;
-    let mut g = Graph::new_undirected();
+    let mut g = Wiring::new_undirected();
     let mut indices = HashMap::new();
-    let mut i = 0;
     for (from, tos) in list {
-        let from_idx = *indices.entry(from).or_insert_with(|| {
-            i += 1;
-            g.add_node(1)
-        });
+        let from_idx = *indices
+            .entry(from.clone())
+            .or_insert_with(|| g.add_node(from));
         for to in tos {
-            let to_idx = *indices.entry(to).or_insert_with(|| {
-                i += 1;
-                g.add_node(1)
-            });
-            g.add_edge(from_idx, to_idx, 1);
+            let to_idx = *indices.entry(to.clone()).or_insert_with(|| g.add_node(to));
+            g.add_edge(from_idx, to_idx, ());
         }
     }
     Ok(g)
 }
 
-fn merge_nodes(g: &mut Graph, a: NodeIndex, b: NodeIndex) {
-    let w1 = g.node_weight(a).unwrap();
-    let w2 = g.node_weight(b).unwrap();
-    let m = g.add_node(w1 + w2);
-    for n in g.neighbors(a).collect::<Vec<_>>() {
-        if n == b {
-            continue;
-        }
-        let e = g.find_edge(a, n).unwrap();
-        let w = *g.edge_weight(e).unwrap();
-        g.add_edge(m, n, w);
-    }
-    for n in g.neighbors(b).collect::<Vec<_>>() {
-        if n == a {
-            continue;
-        }
-        let e = g.find_edge(b, n).unwrap();
-        let mut w = *g.edge_weight(e).unwrap();
-        if let Some(ae) = g.find_edge(a, n) {
-            w += *g.edge_weight(ae).unwrap();
-        }
-        g.update_edge(m, n, w);
+#[aoc_generator(day25)]
+fn input_gen(input: &[u8]) -> Result<Wiring> {
+    parse_wiring(input)
+}
+
+/// The wires to cut to split the components into two groups, and those
+/// groups, all by their names in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disconnection {
+    /// Each wire with its ends in alphabetical order.
+    pub wires: Vec<(String, String)>,
+    pub groups: [Vec<String>; 2],
+}
+
+impl Disconnection {
+    pub fn product(&self) -> usize {
+        self.groups[0].len() * self.groups[1].len()
     }
-    g.remove_node(a);
-    g.remove_node(b);
 }
 
-fn most_tightly_connected(g: &Graph, set: &HashSet<NodeIndex>) -> NodeIndex {
-    let mut ret = None;
-    let mut w = 0;
-    for n in g.node_indices() {
-        let mut w_n = 0;
-        if set.contains(&n) {
-            continue;
-        }
-        for e in g.edges(n) {
-            if !set.contains(&e.target()) {
-                continue;
+impl fmt::Display for Disconnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (cut ", self.product())?;
+        for (i, (a, b)) in self.wires.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
             }
-            w_n += *e.weight();
-        }
-        if w < w_n {
-            w = w_n;
-            ret = Some(n);
+            write!(f, "{a}/{b}")?;
         }
+        write!(f, ")")
     }
-    ret.unwrap()
 }
 
-fn minimum_cut_phase(g: &mut Graph, a: NodeIndex) -> (usize, usize, usize) {
-    let mut set = HashSet::new();
-    let mut order = vec![a];
-    set.insert(a);
-    while set.len() != g.node_count() {
-        let m = most_tightly_connected(g, &set);
-        set.insert(m);
-        order.push(m);
-    }
-    let &[.., n1, n2] = order.as_slice() else {
-        unreachable!()
+/// Cuts the fewest wires that split the components in two.
+pub fn disconnect(g: &Wiring) -> Option<Disconnection> {
+    let cut = stoer_wagner(g, |_| 1)?;
+    let mut wires: Vec<_> = cut
+        .edges
+        .iter()
+        .map(|&e| {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            wire(g, a, b)
+        })
+        .collect();
+    wires.sort();
+    let names = |side: &[NodeIndex]| {
+        let mut v: Vec<_> = side.iter().map(|&n| g[n].clone()).collect();
+        v.sort();
+        v
     };
-    let cut = g.edges(n2).map(|e| *e.weight()).sum();
-    let c1 = *g.node_weight(n2).unwrap();
-    let c2 = set
-        .into_iter()
-        .filter(|&n| n != n2)
-        .map(|n| *g.node_weight(n).unwrap())
-        .sum();
-    merge_nodes(g, n1, n2);
-    (cut, c1, c2)
+    Some(Disconnection {
+        wires,
+        groups: [names(&cut.sides[0]), names(&cut.sides[1])],
+    })
 }
 
-fn minimum_cut(g: &mut Graph, stop_at: usize) -> (usize, usize, usize) {
-    let mut min = usize::MAX;
-    let mut s1 = 0;
-    let mut s2 = 0;
-    while g.node_count() > 1 {
-        let a = g.node_indices().next().unwrap();
-        let (cut, c1, c2) = minimum_cut_phase(g, a);
-        if min > cut {
-            min = cut;
-            s1 = c1;
-            s2 = c2;
-        }
-        if min <= stop_at {
-            break;
+/// The groups of components that only come apart when at least `k` wires
+/// are cut, and the wires between different groups: the fragile links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Groups {
+    pub groups: Vec<Vec<String>>,
+    pub links: Vec<(String, String)>,
+}
+
+/// The fewest wires whose removal disconnects the diagram, 0 if it's
+/// already in several pieces.
+pub fn connectivity(g: &Wiring) -> Option<u64> {
+    edge_connectivity(g, |_| 1)
+}
+
+pub fn k_connected_groups(g: &Wiring, k: u64) -> Groups {
+    let sets = k_edge_connected_subgraphs(g, |_| 1, k);
+    let mut group = vec![0; g.node_count()];
+    for (i, set) in sets.iter().enumerate() {
+        for n in set {
+            group[n.index()] = i;
         }
     }
-    (min, s1, s2)
+    let mut links: Vec<_> = g
+        .edge_indices()
+        .filter_map(|e| {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            (group[a.index()] != group[b.index()]).then(|| wire(g, a, b))
+        })
+        .collect();
+    links.sort();
+    let mut groups: Vec<Vec<_>> = sets
+        .iter()
+        .map(|set| {
+            let mut v: Vec<_> = set.iter().map(|&n| g[n].clone()).collect();
+            v.sort();
+            v
+        })
+        .collect();
+    groups.sort();
+    Groups { groups, links }
+}
+
+fn wire(g: &Wiring, a: NodeIndex, b: NodeIndex) -> (String, String) {
+    let (a, b) = (g[a].clone(), g[b].clone());
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
 }
 
 #[aoc(day25, part1)]
-fn part1(input: &Graph) -> usize {
-    let mut g = input.clone();
-    let (_, c1, c2) = minimum_cut(&mut g, 3);
-    c1 * c2
+fn part1(input: &Wiring) -> Result<Disconnection> {
+    disconnect(input).ok_or_else(|| anyhow!("there are fewer than two components"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mincut::karger_stein;
 
     const EXAMPLE: &[u8] = br#"jqt: rhn xhk nvd
 rsh: frs pzl lsr
@@ -163,6 +175,58 @@ frs: qnr lhk lsr"#;
 
     #[test]
     fn part1_example() {
-        assert_eq!(part1(&input_gen(EXAMPLE).unwrap()), 54);
+        let d = part1(&input_gen(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(d.product(), 54);
+        assert_eq!(d.to_string(), "54 (cut bvb/cmg, hfx/pzl, jqt/nvd)");
+        assert!(d
+            .groups
+            .iter()
+            .any(|g| g == &["bvb", "hfx", "jqt", "ntq", "rhn", "xhk"]));
+    }
+    #[test]
+    fn groups() {
+        let g = input_gen(EXAMPLE).unwrap();
+        assert_eq!(connectivity(&g), Some(3));
+        let groups = k_connected_groups(&g, 3);
+        assert_eq!(groups.groups.len(), 1);
+        assert!(groups.links.is_empty());
+        // every component has only three wires
+        let groups = k_connected_groups(&g, 4);
+        assert_eq!(groups.groups.len(), 15);
+        assert_eq!(groups.links.len(), g.edge_count());
+        assert!(groups.links.contains(&("hfx".to_owned(), "pzl".to_owned())));
+    }
+    #[test]
+    fn disconnected() {
+        let mut input = EXAMPLE.to_vec();
+        input.extend_from_slice(b"\naaa: bbb");
+        let g = input_gen(&input).unwrap();
+        assert_eq!(connectivity(&g), Some(0));
+        let groups = k_connected_groups(&g, 1);
+        assert_eq!(groups.groups.len(), 2);
+        assert_eq!(groups.groups[0], ["aaa", "bbb"]);
+        assert!(groups.links.is_empty());
+        let d = disconnect(&g).unwrap();
+        assert!(d.wires.is_empty());
+        assert_eq!(d.product(), 2 * 15);
+    }
+    #[test]
+    fn cut_partition() {
+        let g = input_gen(EXAMPLE).unwrap();
+        let names = |side: &[NodeIndex]| {
+            let mut v: Vec<_> = side.iter().map(|&n| g[n].as_str()).collect();
+            v.sort();
+            v
+        };
+        for seed in 1..5 {
+            let cut = karger_stein(&g, |_| 1, 20, seed).unwrap();
+            assert_eq!(cut.weight, 3);
+            let mut sides = [names(&cut.sides[0]), names(&cut.sides[1])];
+            sides.sort_by_key(|s| s.len());
+            assert_eq!(sides[0], ["bvb", "hfx", "jqt", "ntq", "rhn", "xhk"]);
+        }
+        let dot = stoer_wagner(&g, |_| 1).unwrap().to_dot(&g, |n| n.clone());
+        assert_eq!(dot.matches("style = dashed").count(), 3);
+        assert!(dot.contains("subgraph cluster_1"));
     }
 }
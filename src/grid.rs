@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+use ndarray::Array2;
+use std::ops::{Deref, DerefMut};
+
+/// A rectangular map of tiles, indexed by `(y, x)` like the underlying `Array2`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    data: Array2<T>,
+}
+
+impl<T> Grid<T> {
+    /// Parses one row per line and one tile per byte, using `tile` to map each byte.
+    ///
+    /// Bytes rejected by `tile` and rows of different widths are reported with
+    /// their 1-based line and column.
+    pub fn parse(input: &[u8], tile: impl Fn(u8) -> Option<T>) -> Result<Self> {
+        let input = match input.iter().rposition(|&b| b != b'\n' && b != b'\r') {
+            Some(last) => &input[..=last],
+            None => bail!("empty map"),
+        };
+        let mut width = None;
+        let mut height = 0;
+        let mut flat = Vec::with_capacity(input.len());
+        for (y, line) in input.split(|&b| b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let w = *width.get_or_insert(line.len());
+            if line.len() != w {
+                bail!("line {} has {} columns, expected {}", y + 1, line.len(), w);
+            }
+            for (x, &b) in line.iter().enumerate() {
+                match tile(b) {
+                    Some(t) => flat.push(t),
+                    None => bail!(
+                        "invalid tile {:?} at line {}, column {}",
+                        b as char,
+                        y + 1,
+                        x + 1
+                    ),
+                }
+            }
+            height += 1;
+        }
+        let width = width.unwrap_or_default();
+        if width == 0 {
+            bail!("empty map");
+        }
+        Ok(Self {
+            data: Array2::from_shape_vec((height, width), flat)?,
+        })
+    }
+
+    pub fn from_array(data: Array2<T>) -> Self {
+        Self { data }
+    }
+
+    pub fn into_array(self) -> Array2<T> {
+        self.data
+    }
+
+    pub fn height(&self) -> usize {
+        self.data.nrows()
+    }
+
+    pub fn width(&self) -> usize {
+        self.data.ncols()
+    }
+
+    /// Whether a possibly out of range position falls inside the map.
+    pub fn in_bounds(&self, (y, x): (i64, i64)) -> bool {
+        y >= 0 && x >= 0 && (y as usize) < self.height() && (x as usize) < self.width()
+    }
+
+    /// Bounds-checked access with signed coordinates.
+    pub fn at(&self, p: (i64, i64)) -> Option<&T> {
+        if self.in_bounds(p) {
+            Some(&self.data[(p.0 as usize, p.1 as usize)])
+        } else {
+            None
+        }
+    }
+
+    /// Maps a position on an infinitely repeating map back into the grid.
+    pub fn wrap(&self, (y, x): (i64, i64)) -> (usize, usize) {
+        (
+            y.rem_euclid(self.height() as i64) as usize,
+            x.rem_euclid(self.width() as i64) as usize,
+        )
+    }
+
+    pub fn at_wrapped(&self, p: (i64, i64)) -> &T {
+        &self.data[self.wrap(p)]
+    }
+
+    /// The up, down, left and right neighbours of `p` that are inside the map.
+    pub fn neighbours(&self, (y, x): (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+        let (h, w) = (self.height(), self.width());
+        [
+            (y.wrapping_sub(1), x),
+            (y + 1, x),
+            (y, x.wrapping_sub(1)),
+            (y, x + 1),
+        ]
+        .into_iter()
+        .filter(move |&(y, x)| y < h && x < w)
+    }
+
+    /// The position of the first tile (in row-major order) matching `pred`.
+    pub fn position(&self, pred: impl Fn(&T) -> bool) -> Option<(usize, usize)> {
+        self.data
+            .indexed_iter()
+            .find(|(_, t)| pred(t))
+            .map(|(p, _)| p)
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        Grid {
+            data: self.data.map(f),
+        }
+    }
+
+    /// Draws the map back as text, one line per row.
+    pub fn render(&self, glyph: impl Fn(&T) -> char) -> String {
+        self.render_with(|_, t| glyph(t))
+    }
+
+    /// Like `render`, but the glyph can also depend on the position.
+    pub fn render_with(&self, glyph: impl Fn((usize, usize), &T) -> char) -> String {
+        let mut ret = String::with_capacity((self.width() + 1) * self.height());
+        for (y, row) in self.data.rows().into_iter().enumerate() {
+            for (x, t) in row.iter().enumerate() {
+                ret.push(glyph((y, x), t));
+            }
+            ret.push('\n');
+        }
+        ret
+    }
+}
+
+impl<T> Deref for Grid<T> {
+    type Target = Array2<T>;
+    fn deref(&self) -> &Array2<T> {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Grid<T> {
+    fn deref_mut(&mut self) -> &mut Array2<T> {
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digit(b: u8) -> Option<u32> {
+        (b as char).to_digit(10)
+    }
+
+    #[test]
+    fn parse_and_render() {
+        let g = Grid::parse(b"123\n456\n", digit).unwrap();
+        assert_eq!((g.height(), g.width()), (2, 3));
+        assert_eq!(g[(1, 0)], 4);
+        assert_eq!(
+            g.render(|&d| char::from_digit(d, 10).unwrap()),
+            "123\n456\n"
+        );
+    }
+    #[test]
+    fn bad_tiles() {
+        let err = Grid::parse(b"123\n4x6", digit).unwrap_err();
+        assert_eq!(err.to_string(), "invalid tile 'x' at line 2, column 2");
+        let err = Grid::parse(b"123\n45", digit).unwrap_err();
+        assert_eq!(err.to_string(), "line 2 has 2 columns, expected 3");
+        assert!(Grid::parse(b"\n", digit).is_err());
+    }
+    #[test]
+    fn neighbours_and_wrap() {
+        let g = Grid::parse(b"123\n456", digit).unwrap();
+        let mut n = g.neighbours((0, 0)).collect::<Vec<_>>();
+        n.sort();
+        assert_eq!(n, vec![(0, 1), (1, 0)]);
+        assert_eq!(g.neighbours((1, 1)).count(), 3);
+        assert_eq!(g.wrap((-1, 5)), (1, 2));
+        assert_eq!(*g.at_wrapped((2, -1)), 3);
+        assert_eq!(g.at((0, 3)), None);
+        assert_eq!(g.at((1, 2)), Some(&6));
+    }
+}
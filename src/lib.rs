@@ -23,5 +23,8 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+pub mod grid;
+pub mod mincut;
+pub mod pathfinding;
 
 aoc_runner_derive::aoc_lib! { year = 2023 }
@@ -0,0 +1,542 @@
+use petgraph::graph::{EdgeIndex, NodeIndex, UnGraph};
+use petgraph::visit::EdgeRef;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write;
+
+/// A split of a graph's nodes into two non-empty sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cut {
+    /// Total weight of the edges crossing the cut.
+    pub weight: u64,
+    /// The two sides, each sorted.
+    pub sides: [Vec<NodeIndex>; 2],
+    /// The crossing edges.
+    pub edges: Vec<EdgeIndex>,
+}
+
+impl Cut {
+    fn new<N, E>(g: &UnGraph<N, E>, weight: impl Fn(&E) -> u64, in_first: &[bool]) -> Cut {
+        let (first, second) = g.node_indices().partition(|n| in_first[n.index()]);
+        let edges: Vec<_> = g
+            .edge_indices()
+            .filter(|&e| {
+                let (a, b) = g.edge_endpoints(e).unwrap();
+                in_first[a.index()] != in_first[b.index()]
+            })
+            .collect();
+        Cut {
+            weight: edges.iter().map(|&e| weight(&g[e])).sum(),
+            sides: [first, second],
+            edges,
+        }
+    }
+
+    /// Renders the graph for Graphviz with one cluster per side and the
+    /// crossing edges dashed in red.
+    pub fn to_dot<N, E>(&self, g: &UnGraph<N, E>, label: impl Fn(&N) -> String) -> String {
+        let mut out = String::from("graph {\n");
+        for (i, side) in self.sides.iter().enumerate() {
+            writeln!(out, "    subgraph cluster_{i} {{").unwrap();
+            for &n in side {
+                writeln!(out, "        {} [ label = {:?} ]", n.index(), label(&g[n])).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        for e in g.edge_indices() {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            let style = if self.edges.contains(&e) {
+                "style = dashed, color = red"
+            } else {
+                ""
+            };
+            writeln!(out, "    {} -- {} [ {style} ]", a.index(), b.index()).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Adds the edges of `b` to `a`, folding parallel edges into one.
+fn merge_nodes(adj: &mut [HashMap<usize, u64>], a: usize, b: usize) {
+    for (n, w) in std::mem::take(&mut adj[b]) {
+        adj[n].remove(&b);
+        if n != a {
+            *adj[a].entry(n).or_default() += w;
+            *adj[n].entry(a).or_default() += w;
+        }
+    }
+}
+
+/// The minimum cut of `g`, or `None` if it has fewer than two nodes.
+///
+/// Stoer–Wagner: each phase grows a set from one node by repeatedly adding
+/// the node most tightly connected to it, picked from a heap of
+/// connection weights, then merges the last two nodes added. The last node
+/// against everything else is a candidate cut, and the lightest candidate
+/// over all phases is a minimum cut. Runs in O(V·E·log V).
+pub fn stoer_wagner<N, E>(g: &UnGraph<N, E>, weight: impl Fn(&E) -> u64) -> Option<Cut> {
+    stoer_wagner_below(g, weight, 0)
+}
+
+/// [`stoer_wagner`], but stopping at the first candidate lighter than
+/// `stop`, which is a cut but not always a minimum one.
+fn stoer_wagner_below<N, E>(
+    g: &UnGraph<N, E>,
+    weight: impl Fn(&E) -> u64,
+    stop: u64,
+) -> Option<Cut> {
+    let n = g.node_count();
+    if n < 2 {
+        return None;
+    }
+    let mut adj = vec![HashMap::new(); n];
+    for e in g.edge_references() {
+        let (a, b) = (e.source().index(), e.target().index());
+        if a != b {
+            *adj[a].entry(b).or_default() += weight(e.weight());
+            *adj[b].entry(a).or_default() += weight(e.weight());
+        }
+    }
+    let mut members: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut key = vec![0; n];
+    let mut added = vec![false; n];
+    while active.len() > 1 {
+        for &v in &active {
+            key[v] = 0;
+            added[v] = false;
+        }
+        let mut heap: BinaryHeap<_> = active.iter().map(|&v| (0, v)).collect();
+        let (mut s, mut t) = (usize::MAX, usize::MAX);
+        while let Some((k, v)) = heap.pop() {
+            if added[v] || k != key[v] {
+                continue;
+            }
+            added[v] = true;
+            (s, t) = (t, v);
+            for (&u, &w) in &adj[v] {
+                if !added[u] {
+                    key[u] += w;
+                    heap.push((key[u], u));
+                }
+            }
+        }
+        if best.as_ref().is_none_or(|(w, _)| key[t] < *w) {
+            best = Some((key[t], members[t].clone()));
+        }
+        if key[t] < stop {
+            break;
+        }
+        merge_nodes(&mut adj, s, t);
+        let moved = std::mem::take(&mut members[t]);
+        members[s].extend(moved);
+        active.retain(|&v| v != t);
+    }
+    let (_, side) = best?;
+    let mut in_first = vec![false; n];
+    for v in side {
+        in_first[v] = true;
+    }
+    Some(Cut::new(g, weight, &in_first))
+}
+
+/// The weight of a minimum cut of `g`: 0 if it's disconnected, `None` if
+/// it has fewer than two nodes.
+pub fn edge_connectivity<N, E>(g: &UnGraph<N, E>, weight: impl Fn(&E) -> u64) -> Option<u64> {
+    stoer_wagner(g, weight).map(|c| c.weight)
+}
+
+/// Splits the nodes into the maximal sets whose induced subgraphs can only
+/// be disconnected by cutting a weight of at least `k`. Nodes that aren't
+/// that well connected to anything end up alone.
+///
+/// Every set found that way is kept whole by any lighter cut, so splitting
+/// along cuts lighter than `k` until none is left finds them all, and
+/// Stoer–Wagner can stop at the first such cut it comes across. Before
+/// looking for a cut, nodes whose edges into the rest of their set
+/// weigh less than `k` are peeled off on their own, which is cheap and
+/// takes care of most sparse graphs. A piece whose minimum cut, that is
+/// its edge connectivity, weighs at least `k` is kept as it is. The sets
+/// are sorted, and so is each of them.
+pub fn k_edge_connected_subgraphs<N, E>(
+    g: &UnGraph<N, E>,
+    weight: impl Fn(&E) -> u64,
+    k: u64,
+) -> Vec<Vec<NodeIndex>> {
+    let mut ret = vec![];
+    let mut todo: Vec<Vec<NodeIndex>> = connected_components(g);
+    let mut keep = vec![false; g.node_count()];
+    while let Some(set) = todo.pop() {
+        for n in &set {
+            keep[n.index()] = true;
+        }
+        for n in peel(g, &weight, k, &set, &mut keep) {
+            ret.push(vec![n]);
+        }
+        // the subgraph's nodes are labelled with where they came from
+        let sub = g.filter_map(|n, _| keep[n.index()].then_some(n), |_, e| Some(weight(e)));
+        for n in &set {
+            keep[n.index()] = false;
+        }
+        for piece in connected_components(&sub) {
+            let piece_set: Vec<_> = piece.iter().map(|&n| sub[n]).collect();
+            if piece.len() == sub.node_count() {
+                match stoer_wagner_below(&sub, |&w| w, k) {
+                    Some(cut) if cut.weight < k => {
+                        todo.extend(cut.sides.map(|side| side.iter().map(|&n| sub[n]).collect()));
+                    }
+                    _ => ret.push(piece_set),
+                }
+            } else {
+                todo.push(piece_set);
+            }
+        }
+    }
+    ret.sort();
+    ret
+}
+
+/// Repeatedly removes the nodes of `set` whose edges to the other nodes
+/// still in `keep` weigh less than `k`, and returns them.
+fn peel<N, E>(
+    g: &UnGraph<N, E>,
+    weight: impl Fn(&E) -> u64,
+    k: u64,
+    set: &[NodeIndex],
+    keep: &mut [bool],
+) -> Vec<NodeIndex> {
+    let mut degree = HashMap::new();
+    for &n in set {
+        let d = g
+            .edges(n)
+            .filter(|e| e.target() != n && keep[e.target().index()])
+            .map(|e| weight(e.weight()))
+            .sum::<u64>();
+        degree.insert(n, d);
+    }
+    let mut peeled: Vec<_> = set.iter().copied().filter(|n| degree[n] < k).collect();
+    for n in &peeled {
+        keep[n.index()] = false;
+    }
+    let mut i = 0;
+    while i < peeled.len() {
+        let n = peeled[i];
+        for e in g.edges(n) {
+            let m = e.target();
+            if keep[m.index()] {
+                let d = degree.get_mut(&m).unwrap();
+                *d -= weight(e.weight());
+                if *d < k {
+                    keep[m.index()] = false;
+                    peeled.push(m);
+                }
+            }
+        }
+        i += 1;
+    }
+    peeled
+}
+
+fn connected_components<N, E>(g: &UnGraph<N, E>) -> Vec<Vec<NodeIndex>> {
+    let mut seen = vec![false; g.node_count()];
+    let mut ret = vec![];
+    for start in g.node_indices() {
+        if seen[start.index()] {
+            continue;
+        }
+        seen[start.index()] = true;
+        let mut component = vec![start];
+        let mut i = 0;
+        while i < component.len() {
+            for n in g.neighbors(component[i]) {
+                if !seen[n.index()] {
+                    seen[n.index()] = true;
+                    component.push(n);
+                }
+            }
+            i += 1;
+        }
+        component.sort();
+        ret.push(component);
+    }
+    ret
+}
+
+/// A xorshift generator, good enough to pick random edges.
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    /// Uniform in (0, 1].
+    fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn find(parent: &mut [usize], mut v: usize) -> usize {
+    while parent[v] != v {
+        parent[v] = parent[parent[v]];
+        v = parent[v];
+    }
+    v
+}
+
+/// A multigraph being contracted: `label[v]` is the super-node each
+/// original node ended up in, and `edges` join super-nodes `0..n`.
+#[derive(Clone)]
+struct Contraction {
+    n: usize,
+    label: Vec<usize>,
+    edges: Vec<(usize, usize, u64)>,
+}
+
+impl Contraction {
+    /// Contracts random edges, each picked with a probability proportional
+    /// to its weight, until only `target` super-nodes are left.
+    fn contract(&self, target: usize, rng: &mut Rng) -> Contraction {
+        // sorting by an exponential key with rate `w` orders the edges like
+        // repeatedly drawing one proportionally to its weight
+        let mut order: Vec<_> = self
+            .edges
+            .iter()
+            .map(|&(a, b, w)| (-rng.unit().ln() / w as f64, a, b))
+            .collect();
+        order.sort_by(|x, y| x.0.total_cmp(&y.0));
+        let mut parent: Vec<_> = (0..self.n).collect();
+        let mut n = self.n;
+        for (_, a, b) in order {
+            if n <= target {
+                break;
+            }
+            let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+            if a != b {
+                parent[b] = a;
+                n -= 1;
+            }
+        }
+        let mut renumber = vec![usize::MAX; self.n];
+        let mut next = 0;
+        for v in 0..self.n {
+            let r = find(&mut parent, v);
+            if renumber[r] == usize::MAX {
+                renumber[r] = next;
+                next += 1;
+            }
+            renumber[v] = renumber[r];
+        }
+        let mut merged: HashMap<(usize, usize), u64> = HashMap::new();
+        for &(a, b, w) in &self.edges {
+            let (a, b) = (renumber[a], renumber[b]);
+            if a != b {
+                *merged.entry((a.min(b), a.max(b))).or_default() += w;
+            }
+        }
+        // sorted so that runs only depend on the seed
+        let mut edges: Vec<_> = merged.into_iter().map(|((a, b), w)| (a, b, w)).collect();
+        edges.sort_unstable();
+        Contraction {
+            n: next,
+            label: self.label.iter().map(|&l| renumber[l]).collect(),
+            edges,
+        }
+    }
+
+    fn weight(&self) -> u64 {
+        self.edges.iter().map(|e| e.2).sum()
+    }
+
+    /// One run of the recursive contraction: shrink to about `n / √2`
+    /// super-nodes twice independently and keep the better result.
+    fn recurse(&self, rng: &mut Rng) -> Contraction {
+        // without edges left every split is a minimum cut
+        if self.n <= 6 || self.edges.is_empty() {
+            return self.contract(2, rng);
+        }
+        let target = (1.0 + self.n as f64 / std::f64::consts::SQRT_2).ceil() as usize;
+        let a = self.contract(target, rng).recurse(rng);
+        let b = self.contract(target, rng).recurse(rng);
+        if a.weight() <= b.weight() {
+            a
+        } else {
+            b
+        }
+    }
+}
+
+/// A minimum cut of `g` found with high probability, or `None` if it has
+/// fewer than two nodes.
+///
+/// Karger–Stein: each trial contracts random edges recursively and finds a
+/// minimum cut with probability Ω(1 / log V), so the chance of missing it
+/// drops geometrically with `trials`. The lightest cut seen is returned;
+/// it is always a valid cut, just not necessarily a minimum one.
+pub fn karger_stein<N, E>(
+    g: &UnGraph<N, E>,
+    weight: impl Fn(&E) -> u64,
+    trials: usize,
+    seed: u64,
+) -> Option<Cut> {
+    let n = g.node_count();
+    if n < 2 {
+        return None;
+    }
+    let start = Contraction {
+        n,
+        label: (0..n).collect(),
+        edges: g
+            .edge_references()
+            .map(|e| (e.source().index(), e.target().index(), weight(e.weight())))
+            .filter(|&(a, b, w)| a != b && w > 0)
+            .collect(),
+    };
+    let mut rng = Rng(seed | 1);
+    let mut best: Option<Contraction> = None;
+    for _ in 0..trials.max(1) {
+        let c = start.recurse(&mut rng);
+        if best.as_ref().is_none_or(|b| c.weight() < b.weight()) {
+            best = Some(c);
+        }
+    }
+    let best = best?;
+    let in_first: Vec<_> = best.label.iter().map(|&l| l == 0).collect();
+    Some(Cut::new(g, weight, &in_first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two triangles joined by the single edge 2 - 3
+    fn barbell() -> UnGraph<(), u64> {
+        UnGraph::from_edges([
+            (0, 1, 1),
+            (1, 2, 1),
+            (0, 2, 1),
+            (2, 3, 1),
+            (3, 4, 1),
+            (4, 5, 1),
+            (3, 5, 1),
+        ])
+    }
+
+    #[test]
+    fn barbell_cut() {
+        let g = barbell();
+        for cut in [stoer_wagner(&g, |&w| w), karger_stein(&g, |&w| w, 10, 1)] {
+            let mut cut = cut.unwrap();
+            cut.sides.sort();
+            assert_eq!(cut.weight, 1);
+            assert_eq!(cut.edges, [EdgeIndex::new(3)]);
+            let side = |v: &[u32]| {
+                v.iter()
+                    .map(|&i| NodeIndex::new(i as usize))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(cut.sides, [side(&[0, 1, 2]), side(&[3, 4, 5])]);
+        }
+    }
+    #[test]
+    fn weights_and_components() {
+        // the heavy edge must not be cut even though it's the only one at 3
+        let g: UnGraph<(), u64> = UnGraph::from_edges([(0, 1, 5), (1, 2, 2), (2, 0, 2), (2, 3, 9)]);
+        assert_eq!(stoer_wagner(&g, |&w| w).unwrap().weight, 4);
+        assert_eq!(karger_stein(&g, |&w| w, 10, 7).unwrap().weight, 4);
+        let mut g: UnGraph<(), u64> = UnGraph::from_edges([(0, 1, 1), (2, 3, 1)]);
+        assert_eq!(stoer_wagner(&g, |&w| w).unwrap().weight, 0);
+        assert_eq!(karger_stein(&g, |&w| w, 1, 7).unwrap().weight, 0);
+        g.add_node(());
+        assert!(stoer_wagner(&g, |&w| w).unwrap().edges.is_empty());
+        assert_eq!(stoer_wagner(&UnGraph::<(), u64>::default(), |&w| w), None);
+    }
+    #[test]
+    fn connectivity() {
+        let mut g = barbell();
+        assert_eq!(edge_connectivity(&g, |&w| w), Some(1));
+        let sets = |g: &UnGraph<(), u64>, k| {
+            k_edge_connected_subgraphs(g, |&w| w, k)
+                .into_iter()
+                .map(|s| s.into_iter().map(|n| n.index()).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sets(&g, 1), [vec![0, 1, 2, 3, 4, 5]]);
+        assert_eq!(sets(&g, 2), [vec![0, 1, 2], vec![3, 4, 5]]);
+        assert_eq!(sets(&g, 3).len(), 6);
+        // a separate edge and an isolated node
+        g.extend_with_edges([(6, 7, 2)]);
+        g.add_node(());
+        assert_eq!(edge_connectivity(&g, |&w| w), Some(0));
+        assert_eq!(
+            sets(&g, 2),
+            [vec![0, 1, 2], vec![3, 4, 5], vec![6, 7], vec![8]]
+        );
+        assert_eq!(
+            edge_connectivity(&UnGraph::<(), u64>::default(), |&w| w),
+            None
+        );
+        assert!(sets(&UnGraph::default(), 1).is_empty());
+    }
+    #[test]
+    fn many_groups() {
+        // 30 cliques of 10 in a row, joined by single edges, and a ring of
+        // 200 nodes hanging off the first one
+        let mut g = UnGraph::<(), u64>::default();
+        for c in 0..30 {
+            for a in 0..10 {
+                for b in a + 1..10 {
+                    g.extend_with_edges([(c * 10 + a, c * 10 + b, 1)]);
+                }
+            }
+            if c > 0 {
+                g.extend_with_edges([(c * 10 - 1, c * 10, 1)]);
+            }
+        }
+        for i in 0..200 {
+            g.extend_with_edges([(300 + i, 300 + (i + 1) % 200, 1)]);
+        }
+        g.extend_with_edges([(0, 300, 1)]);
+        let sets = k_edge_connected_subgraphs(&g, |&w| w, 3);
+        assert_eq!(sets.len(), 30 + 200);
+        for (c, set) in sets.iter().take(30).enumerate() {
+            assert_eq!(set.len(), 10, "clique {c}");
+            assert_eq!(set[0].index(), c * 10);
+        }
+    }
+    #[test]
+    fn random_graphs_agree() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..100 {
+            let n = 2 + rng.next() as usize % 10;
+            let mut g = UnGraph::<(), u64>::default();
+            for _ in 0..n {
+                g.add_node(());
+            }
+            for _ in 0..rng.next() % 30 {
+                let (a, b) = (rng.next() as usize % n, rng.next() as usize % n);
+                g.add_edge(NodeIndex::new(a), NodeIndex::new(b), 1 + rng.next() % 4);
+            }
+            // brute force over every split keeping node 0 on the first side
+            let brute = (0..1u32 << (n - 1))
+                .map(|mask| {
+                    let in_first: Vec<_> =
+                        (0..n).map(|v| v == 0 || mask >> (v - 1) & 1 == 0).collect();
+                    in_first
+                })
+                .filter(|s| s.contains(&false))
+                .map(|s| Cut::new(&g, |&w| w, &s).weight)
+                .min()
+                .unwrap();
+            let sw = stoer_wagner(&g, |&w| w).unwrap();
+            assert_eq!(sw.weight, brute);
+            assert!(sw.sides.iter().all(|s| !s.is_empty()));
+            assert_eq!(
+                karger_stein(&g, |&w| w, 30, rng.next()).unwrap().weight,
+                brute
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+use std::ops::Add;
+
+/// A cheapest path found by [`astar`] or [`dijkstra`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<S, C> {
+    pub cost: C,
+    /// Every state from the start to the goal, both included.
+    pub states: Vec<S>,
+}
+
+struct Node<S, C> {
+    state: S,
+    cost: C,
+    pred: Option<usize>,
+    closed: bool,
+}
+
+/// A* search over an implicit graph of states.
+///
+/// `successors` yields the states reachable from a state with the cost of
+/// the move, and `heuristic` must be consistent: it never decreases by more
+/// than the cost of a move, and is zero on goals. Each state is stored once
+/// together with the index of its best predecessor, so the path is only
+/// rebuilt when a goal is reached.
+pub fn astar<S, C, I>(
+    starts: impl IntoIterator<Item = S>,
+    mut successors: impl FnMut(&S) -> I,
+    mut heuristic: impl FnMut(&S) -> C,
+    mut is_goal: impl FnMut(&S) -> bool,
+) -> Option<Path<S, C>>
+where
+    S: Clone + Eq + Hash,
+    C: Copy + Ord + Default + Add<Output = C>,
+    I: IntoIterator<Item = (S, C)>,
+{
+    let mut nodes: Vec<Node<S, C>> = Vec::new();
+    let mut ids: HashMap<S, usize> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for s in starts {
+        if let Entry::Vacant(e) = ids.entry(s.clone()) {
+            e.insert(nodes.len());
+            queue.push(Reverse((heuristic(&s), C::default(), nodes.len())));
+            nodes.push(Node {
+                state: s,
+                cost: C::default(),
+                pred: None,
+                closed: false,
+            });
+        }
+    }
+    while let Some(Reverse((_, cost, id))) = queue.pop() {
+        if nodes[id].closed || cost > nodes[id].cost {
+            continue;
+        }
+        nodes[id].closed = true;
+        if is_goal(&nodes[id].state) {
+            let mut states = vec![];
+            let mut cur = Some(id);
+            while let Some(i) = cur {
+                states.push(nodes[i].state.clone());
+                cur = nodes[i].pred;
+            }
+            states.reverse();
+            return Some(Path { cost, states });
+        }
+        for (n, step) in successors(&nodes[id].state) {
+            let n_cost = cost + step;
+            let n_id = match ids.entry(n) {
+                Entry::Occupied(e) => {
+                    let n_id = *e.get();
+                    if nodes[n_id].closed || nodes[n_id].cost <= n_cost {
+                        continue;
+                    }
+                    nodes[n_id].cost = n_cost;
+                    nodes[n_id].pred = Some(id);
+                    n_id
+                }
+                Entry::Vacant(e) => {
+                    let n_id = nodes.len();
+                    nodes.push(Node {
+                        state: e.key().clone(),
+                        cost: n_cost,
+                        pred: Some(id),
+                        closed: false,
+                    });
+                    e.insert(n_id);
+                    n_id
+                }
+            };
+            let estimate = n_cost + heuristic(&nodes[n_id].state);
+            queue.push(Reverse((estimate, n_cost, n_id)));
+        }
+    }
+    None
+}
+
+/// [`astar`] without a heuristic.
+pub fn dijkstra<S, C, I>(
+    starts: impl IntoIterator<Item = S>,
+    successors: impl FnMut(&S) -> I,
+    is_goal: impl FnMut(&S) -> bool,
+) -> Option<Path<S, C>>
+where
+    S: Clone + Eq + Hash,
+    C: Copy + Ord + Default + Add<Output = C>,
+    I: IntoIterator<Item = (S, C)>,
+{
+    astar(starts, successors, |_| C::default(), is_goal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 -1-> 1 -1-> 2 -1-> 3, plus a direct but more expensive 0 -5-> 3
+    fn succ(&n: &u32) -> Vec<(u32, u32)> {
+        match n {
+            0 => vec![(1, 1), (3, 5)],
+            1 => vec![(2, 1)],
+            2 => vec![(3, 1)],
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn cheapest_path() {
+        let p = dijkstra([0], succ, |&n| n == 3).unwrap();
+        assert_eq!(p.cost, 3);
+        assert_eq!(p.states, vec![0, 1, 2, 3]);
+        let p = astar([0], succ, |&n| 3 - n, |&n| n == 3).unwrap();
+        assert_eq!(p.cost, 3);
+        assert_eq!(p.states, vec![0, 1, 2, 3]);
+    }
+    #[test]
+    fn unreachable_goal() {
+        assert_eq!(dijkstra([1], succ, |&n| n == 0), None);
+    }
+}